@@ -0,0 +1,66 @@
+//! Latencies that matter to embedders evaluating one script per request.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lox::api::{self, Interpreter, ReplCompiler, Value};
+
+const PRELUDE: &str = "
+var greeting = \"hello\";
+var limit = 10;
+{
+    var i = 0;
+    while (i < limit) i = i + 1;
+}
+";
+
+const SCRIPT: &str = "var answer = limit * 4 + 2;";
+
+fn phases(c: &mut Criterion) {
+    let prelude = api::compile(PRELUDE.as_bytes()).unwrap();
+    let script = api::compile(SCRIPT.as_bytes()).unwrap();
+
+    c.bench_function("build empty interpreter", |b| {
+        b.iter(|| Interpreter::builder().build().unwrap())
+    });
+    c.bench_function("build with globals and prelude", |b| {
+        b.iter(|| {
+            Interpreter::builder()
+                .global("version", Value::Number(1.0))
+                .prelude(prelude.clone())
+                .build()
+                .unwrap()
+        })
+    });
+    c.bench_function("compile script", |b| {
+        b.iter(|| api::compile(SCRIPT.as_bytes()).unwrap())
+    });
+    c.bench_function("compile script reusing buffers", |b| {
+        let mut compiler = ReplCompiler::new();
+        b.iter(|| {
+            compiler.compile(SCRIPT.as_bytes()).unwrap();
+        })
+    });
+    c.bench_function("execute compiled script", |b| {
+        let mut interpreter = Interpreter::builder()
+            .prelude(prelude.clone())
+            .build()
+            .unwrap();
+        b.iter(|| interpreter.interpret(&script).unwrap())
+    });
+}
+
+fn time_to_first_statement(c: &mut Criterion) {
+    let prelude = api::compile(PRELUDE.as_bytes()).unwrap();
+    c.bench_function("time to first statement", |b| {
+        b.iter(|| {
+            let mut interpreter = Interpreter::builder()
+                .prelude(prelude.clone())
+                .build()
+                .unwrap();
+            let script = api::compile(SCRIPT.as_bytes()).unwrap();
+            interpreter.interpret(&script).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, phases, time_to_first_statement);
+criterion_main!(benches);
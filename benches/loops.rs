@@ -0,0 +1,39 @@
+//! Throughput of the interpreter on scripts that spend their time in
+//! loops, where copying values around dominates.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lox::api::{self, Interpreter};
+
+const COUNT: &str = "
+var sum = 0;
+for (var i = 0; i < 10000; i = i + 1) sum = sum + i;
+";
+
+const CONCATENATE: &str = "
+var text = \"\";
+for (var i = 0; i < 1000; i = i + 1) text = text + \"x\";
+";
+
+const FIBONACCI: &str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+fib(15);
+";
+
+fn loops(c: &mut Criterion) {
+    for (name, source) in [
+        ("count", COUNT),
+        ("concatenate", CONCATENATE),
+        ("fibonacci", FIBONACCI),
+    ] {
+        let script = api::compile(source.as_bytes()).unwrap();
+        c.bench_function(name, |b| {
+            b.iter(|| Interpreter::new().interpret(&script).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, loops);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Scanning must report malformed input through `ScanErrors`, never panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = lox::unstable::scanner::scan_tokens(data);
+});
@@ -0,0 +1,103 @@
+//! The stable embedding API.
+//!
+//! Everything named here follows semantic versioning: it only changes in
+//! incompatible ways in a new major release. The syntax tree reachable
+//! through [`Program`]'s fields is the exception; it belongs to
+//! [`crate::unstable`].
+
+use std::{error, fmt};
+
+use crate::{
+    parser::{self, ParseErrors, Warning},
+    scanner::{self, ScanErrors, ScannerOptions, Token},
+    unstable::{lower, resolve},
+};
+
+pub use crate::{
+    ast::{BinaryOp, Program},
+    environment::Cell,
+    intern::Symbol,
+    interpreter::{
+        Callable, DivisionByZero, Error as RuntimeError, ErrorKind as RuntimeErrorKind, HostValue,
+        Interpreter, InterpreterBuilder, LoxClass, LoxFunction, LoxInstance, LoxList, LoxTuple,
+        NativeFn, NativeFunction, Operators, OutputBuffer, StackFrame, StackTrace, Value,
+        DEFAULT_MAX_CALL_DEPTH,
+    },
+};
+
+#[derive(Debug)]
+pub enum CompileError {
+    Scan(ScanErrors),
+    Parse(ParseErrors),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Scan(errors) => write!(f, "{errors}"),
+            Self::Parse(errors) => write!(f, "{errors}"),
+        }
+    }
+}
+
+impl error::Error for CompileError {}
+
+/// Scans, parses, lowers and resolves `source` into a program ready to be
+/// interpreted. If the script ends with an expression that has no `;`,
+/// interpreting the program returns its value.
+pub fn compile(source: &[u8]) -> Result<Program, CompileError> {
+    let tokens = scanner::scan_tokens(source).map_err(CompileError::Scan)?;
+    let mut program = parser::parse(tokens).map_err(CompileError::Parse)?;
+    lower::lower(&mut program);
+    resolve::resolve(&mut program);
+    Ok(program)
+}
+
+/// Like [`compile`], but for a line of REPL input. REPL input follows the
+/// same grammar as a script, but interpreting it also returns the value of
+/// an expression statement that ends it, unless that assigns.
+pub fn compile_repl(source: &[u8]) -> Result<Program, CompileError> {
+    let tokens = scanner::scan_tokens(source).map_err(CompileError::Scan)?;
+    let mut program = parser::parse_repl(tokens).map_err(CompileError::Parse)?;
+    lower::lower(&mut program);
+    resolve::resolve(&mut program);
+    Ok(program)
+}
+
+/// Compiles REPL input one line after another. Each line reuses the token
+/// buffer and expression arena of the line before, rather than allocating
+/// its own.
+#[derive(Debug, Default)]
+pub struct ReplCompiler {
+    tokens: Vec<Token>,
+    program: Program,
+}
+
+impl ReplCompiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles `source` like [`compile_repl`]. The program is overwritten
+    /// by the next call.
+    pub fn compile(&mut self, source: &[u8]) -> Result<&Program, CompileError> {
+        self.compile_with_warnings(source)
+            .map(|(program, _)| program)
+    }
+
+    /// Like [`compile`](Self::compile), but also returns the resolver's
+    /// [`Warning`]s about local variables that shadow or redeclare others,
+    /// in source order. Warnings belong to [`crate::unstable`].
+    pub fn compile_with_warnings(
+        &mut self,
+        source: &[u8],
+    ) -> Result<(&Program, Vec<Warning>), CompileError> {
+        scanner::scan_tokens_into(source, &ScannerOptions::default(), &mut self.tokens)
+            .map_err(CompileError::Scan)?;
+        parser::parse_repl_into(&mut self.tokens, &mut self.program)
+            .map_err(CompileError::Parse)?;
+        lower::lower(&mut self.program);
+        let warnings = resolve::resolve_with_warnings(&mut self.program);
+        Ok((&self.program, warnings))
+    }
+}
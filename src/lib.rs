@@ -0,0 +1,8 @@
+#![warn(rust_2018_idioms)]
+
+pub mod api;
+pub mod unstable;
+
+// Let the implementation refer to its own modules as `crate::parser` and so
+// on, independent of where they are exposed.
+use unstable::{ast, environment, gc, intern, interpreter, parser, scanner};
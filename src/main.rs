@@ -1,3 +1,358 @@
 #![warn(rust_2018_idioms)]
 
-fn main() {}
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use lox::{
+    api::{DivisionByZero, Interpreter, InterpreterBuilder, Program, ReplCompiler, Value},
+    unstable::{
+        ast::ExprKind,
+        ast_printer, dead_code, lower, parser, resolve,
+        scanner::{self, ScannerOptions},
+        stats::Stats,
+    },
+};
+use rustyline::{
+    error::ReadlineError, Cmd, ConditionalEventHandler, DefaultEditor, Event, EventContext,
+    EventHandler, KeyEvent, Movement, RepeatCount,
+};
+
+/// A tree-walk interpreter for the Lox programming language.
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+#[command(group(ArgGroup::new("source").args(["script", "eval"])))]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Script to run. Starts an interactive prompt if omitted, unless
+    /// `--eval` gives the code to run.
+    script: Option<PathBuf>,
+
+    /// Run CODE, given on the command line, instead of a script.
+    #[arg(short, long, value_name = "CODE")]
+    eval: Option<String>,
+
+    /// Print the syntax tree instead of running the program.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "sexpr"
+    )]
+    ast: Option<AstFormat>,
+
+    /// Print each grammar rule the parser applies to the script, and the
+    /// tokens it consumes, to standard error.
+    #[arg(long, requires = "source")]
+    trace_parse: bool,
+
+    /// Print the tokens the scanner finds in the script, one per line with
+    /// its position, type and text, instead of running it.
+    #[arg(long, requires = "source")]
+    tokens: bool,
+
+    /// Collect garbage before every allocation. Slow; meant for testing
+    /// the garbage collector.
+    #[arg(long)]
+    gc_stress: bool,
+
+    /// What dividing by zero does.
+    #[arg(long, value_enum, value_name = "BEHAVIOR", default_value = "ieee")]
+    division_by_zero: DivisionMode,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Report function sizes, nesting, complexity and comment density.
+    Stats { script: PathBuf },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AstFormat {
+    /// Fully parenthesized, Lisp-like expressions.
+    Sexpr,
+    /// JSON, for consumption by external tools.
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DivisionMode {
+    /// Give infinity or NaN, as jlox does.
+    Ieee,
+    /// Stop with a runtime error.
+    Error,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let source = match (&cli.command, &cli.script, &cli.eval) {
+        (Some(Command::Stats { script }), _, _) => return print_stats(script),
+        (None, Some(script), _) => fs::read(script)?,
+        (None, None, Some(code)) => code.as_bytes().to_vec(),
+        (None, None, None) => return run_prompt(&cli),
+    };
+    if cli.tokens {
+        print_tokens(&source)
+    } else {
+        run_source(&cli, &source)
+    }
+}
+
+fn print_stats(path: &Path) -> Result<()> {
+    let source = fs::read(path)?;
+    let options = ScannerOptions {
+        keep_comments: true,
+        ..ScannerOptions::default()
+    };
+    let tokens = scanner::scan_tokens_with_options(&source, &options)?;
+    let program = parser::parse_lossless(tokens)?;
+    println!("{}", Stats::new(&source, &program));
+    Ok(())
+}
+
+fn print_tokens(source: &[u8]) -> Result<()> {
+    for token in scanner::scan_tokens(source)? {
+        let position = format!("{}:{}", token.span.line, token.span.column);
+        let token_type = format!("{:?}", token.token_type);
+        println!("{position:<8} {token_type:<16} {}", token.lexeme);
+    }
+    Ok(())
+}
+
+/// Runs a whole script, from a file or the command line.
+fn run_source(cli: &Cli, source: &[u8]) -> Result<()> {
+    let (mut program, warnings) = if cli.trace_parse {
+        let (program, trace) = parser::parse_traced(scanner::scan_tokens(source)?);
+        eprint!("{trace}");
+        (program?, None)
+    } else {
+        let (program, warnings) = parser::parse_with_warnings(scanner::scan_tokens(source)?)?;
+        let warnings = warnings
+            .iter()
+            .map(|warning| (warning.token().span.start, warning.to_string()));
+        let dead_code = dead_code::find_dead_code(&program);
+        let dead_code = dead_code
+            .iter()
+            .map(|dead| (dead.span().start, dead.to_string()));
+        let warnings: Vec<_> = warnings.chain(dead_code).collect();
+        (program, Some(warnings))
+    };
+    // Print the program as written, before lowering rewrites `for` loops.
+    if let Some(format) = cli.ast {
+        print_ast(format, &program)?;
+    }
+    lower::lower(&mut program);
+    let resolved = resolve::resolve_with_warnings(&mut program);
+    if let Some(mut warnings) = warnings {
+        let resolved = resolved
+            .iter()
+            .map(|warning| (warning.token().span.start, warning.to_string()));
+        warnings.extend(resolved);
+        warnings.sort_by_key(|&(start, _)| start);
+        for (_, warning) in warnings {
+            eprintln!("{warning}");
+        }
+    }
+    if cli.ast.is_some() {
+        return Ok(());
+    }
+    let mut interpreter = interpreter(cli).build()?;
+    run(&mut interpreter, &program, source)
+}
+
+/// An interpreter configured as the command line asks.
+fn interpreter(cli: &Cli) -> InterpreterBuilder {
+    let division_by_zero = match cli.division_by_zero {
+        DivisionMode::Ieee => DivisionByZero::Ieee,
+        DivisionMode::Error => DivisionByZero::Error,
+    };
+    Interpreter::builder()
+        .gc_stress(cli.gc_stress)
+        .division_by_zero(division_by_zero)
+}
+
+fn run_prompt(cli: &Cli) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    editor.bind_sequence(
+        KeyEvent::ctrl('D'),
+        EventHandler::Conditional(Box::new(EndOfInput)),
+    );
+    let history = history_path();
+    if let Some(history) = &history {
+        // There is no history before the first session.
+        let _ = editor.load_history(history);
+    }
+    let mut compiler = ReplCompiler::new();
+    // Expressions registered with `:watch`, printed after every input.
+    let mut watches: Vec<(String, Program)> = Vec::new();
+    let mut session = Session::default();
+    // One interpreter for the whole session, so that what one input
+    // declares, later inputs can use.
+    let mut interpreter = interpreter(cli).build()?;
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            // Ctrl-C discards the line being edited and starts over.
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-D ends the session, once `EndOfInput` has discarded
+            // any partly typed line.
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+        if line.trim() == ":quit" {
+            break;
+        }
+        if let Some(expression) = command_argument(&line, ":watch") {
+            match compile_watch(expression) {
+                Ok(program) => watches.push((expression.trim().to_owned(), program)),
+                Err(error) => eprintln!("{error}"),
+            }
+            continue;
+        }
+        if !line.trim().is_empty() {
+            session.inputs += 1;
+        }
+        let result = match cli.ast {
+            Some(format) => scanner::scan_tokens(line.as_bytes())
+                .map_err(anyhow::Error::from)
+                .and_then(|tokens| Ok(parser::parse_repl(tokens)?))
+                .and_then(|program| print_ast(format, &program)),
+            None => compiler
+                .compile_with_warnings(line.as_bytes())
+                .map_err(anyhow::Error::from)
+                .and_then(|(program, warnings)| {
+                    for warning in warnings {
+                        eprintln!("{warning}");
+                    }
+                    run(&mut interpreter, program, line.as_bytes())
+                }),
+        };
+        if let Err(error) = result {
+            session.errors += 1;
+            eprintln!("{error}");
+        }
+        if cli.ast.is_none() {
+            for (expression, program) in &watches {
+                match interpreter.interpret(program) {
+                    Ok(Some(value)) => println!("{expression} = {value}"),
+                    Ok(None) => println!("{expression}: No value."),
+                    Err(error) => println!("{expression}: {error}"),
+                }
+            }
+        }
+    }
+    if let Some(history) = &history {
+        if let Err(error) = editor.save_history(history) {
+            eprintln!("Could not save the prompt's history: {error}");
+        }
+    }
+    println!("Goodbye! {session}");
+    Ok(())
+}
+
+/// Ctrl-D at the prompt: discards the line being typed, if there is one,
+/// or else ends the session. By default it deletes the character under
+/// the cursor of a non-empty line instead.
+struct EndOfInput;
+
+impl ConditionalEventHandler for EndOfInput {
+    fn handle(
+        &self,
+        _: &Event,
+        _: RepeatCount,
+        _: bool,
+        context: &EventContext<'_>,
+    ) -> Option<Cmd> {
+        Some(if context.line().is_empty() {
+            Cmd::EndOfFile
+        } else {
+            Cmd::Kill(Movement::WholeLine)
+        })
+    }
+}
+
+/// Where the prompt keeps the lines entered, so that later sessions can
+/// recall them: `.lox_history` in the home directory, if there is one.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".lox_history"))
+}
+
+/// What happened during a prompt session, reported when it ends.
+#[derive(Default)]
+struct Session {
+    inputs: usize,
+    errors: usize,
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        write!(
+            f,
+            "({} input{}, {} error{})",
+            self.inputs,
+            plural(self.inputs),
+            self.errors,
+            plural(self.errors)
+        )
+    }
+}
+
+/// What follows `command` on `line`, if the line starts with that REPL
+/// command. A longer word that merely starts with it, like `:watchx`, is
+/// not the command.
+fn command_argument<'a>(line: &'a str, command: &str) -> Option<&'a str> {
+    let argument = line.trim().strip_prefix(command)?;
+    match argument.chars().next() {
+        None => Some(argument),
+        Some(first) if first.is_whitespace() => Some(argument),
+        Some(_) => None,
+    }
+}
+
+fn compile_watch(expression: &str) -> Result<Program> {
+    if expression.trim().is_empty() {
+        anyhow::bail!("Usage: :watch <expression>");
+    }
+    let tokens = scanner::scan_tokens(expression.as_bytes())?;
+    Ok(parser::parse_expression(tokens)?)
+}
+
+/// Prints `program` as `--ast` asks.
+fn print_ast(format: AstFormat, program: &Program) -> Result<()> {
+    match format {
+        AstFormat::Sexpr => {
+            for statement in &program.statements {
+                println!("{}", ast_printer::print_statement(program, statement));
+            }
+            if let Some(result) = program.result {
+                println!("{}", ast_printer::print_expr(program, result));
+            }
+        }
+        AstFormat::Json => println!("{}", serde_json::to_string_pretty(program)?),
+    }
+    Ok(())
+}
+
+/// Runs `program`, compiled from `source`.
+fn run(interpreter: &mut Interpreter, program: &Program, source: &[u8]) -> Result<()> {
+    let result = interpreter
+        .interpret(program)
+        .map_err(|error| anyhow::anyhow!("{}", error.report(source)))?;
+    match (result, program.result) {
+        // A call made for its effect, such as `f();`, has nothing to show.
+        (Some(Value::Nil), Some(expr)) if matches!(program[expr].kind, ExprKind::Call { .. }) => {}
+        (Some(value), _) => println!("{value}"),
+        (None, _) => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! The frontend and interpreter internals: scanner, parser, syntax tree and
+//! tree-walking evaluator.
+//!
+//! These modules are public so that tools such as formatters and linters
+//! can build on them, but they change whenever the implementation needs
+//! them to, including in patch releases. Embedders that only run Lox code
+//! should use [`crate::api`] instead.
+
+pub mod ast;
+pub mod ast_printer;
+pub mod dead_code;
+pub mod environment;
+pub mod gc;
+pub mod incremental;
+pub mod intern;
+pub mod interpreter;
+pub mod lower;
+pub mod parser;
+pub mod resolve;
+pub mod scanner;
+mod stack;
+pub mod stats;
+pub mod unparse;
+pub mod validate;
@@ -0,0 +1,588 @@
+use std::{
+    fmt,
+    ops::{Index, IndexMut},
+    rc::Rc,
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{
+    intern::Symbol,
+    scanner::{Span, Token, TokenType},
+};
+
+/// A parsed program. Expressions are stored in a flat arena and refer to
+/// their operands by [`ExprId`], which keeps them contiguous in memory and
+/// lets passes visit every expression by walking a vector.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    /// A final expression with no `;`, evaluated after the statements.
+    /// Its value is the program's result: the REPL and `lox` print it,
+    /// and [`Interpreter::interpret`](crate::interpreter::Interpreter::interpret)
+    /// returns it.
+    pub result: Option<ExprId>,
+    /// Ordinary comments, in source order. Only
+    /// [`parse_lossless`](crate::parser::parse_lossless) keeps them.
+    pub comments: Vec<Comment>,
+    exprs: Vec<Expr>,
+    #[serde(skip)]
+    statement_count: u32,
+    /// Where each expression finds the variable it refers to, indexed by
+    /// [`ExprId`]. `None` until the program is
+    /// [resolved](crate::unstable::resolve::resolve).
+    #[serde(skip)]
+    bindings: Option<Vec<Binding>>,
+}
+
+/// Where a variable reference finds its variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// A local variable, declared `depth` scopes out from the reference,
+    /// in the `slot`th place among that scope's declarations.
+    Local {
+        depth: usize,
+        slot: usize,
+    },
+    Global,
+}
+
+impl Program {
+    /// Removes every statement and expression but keeps the memory they
+    /// used, so the program can be parsed into again.
+    pub fn clear(&mut self) {
+        self.statements.clear();
+        self.result = None;
+        self.comments.clear();
+        self.exprs.clear();
+        self.statement_count = 0;
+        self.bindings = None;
+    }
+
+    pub fn add_expr(&mut self, expr: Expr) -> ExprId {
+        let id = u32::try_from(self.exprs.len()).expect("expression arena is full");
+        self.exprs.push(expr);
+        ExprId(id)
+    }
+
+    /// Allocates the id for a new statement. Unlike expressions, statements
+    /// are stored inline in their parents, so this only counts them.
+    pub fn next_statement_id(&mut self) -> StatementId {
+        let id = self.statement_count;
+        self.statement_count = id.checked_add(1).expect("too many statements");
+        StatementId(id)
+    }
+
+    /// Where `expr`, a variable or assignment, finds its variable, or
+    /// `None` if the program has not been resolved.
+    pub fn binding(&self, expr: ExprId) -> Option<Binding> {
+        let bindings = self.bindings.as_ref()?;
+        // Expressions added since resolving refer to no local.
+        Some(
+            bindings
+                .get(expr.0 as usize)
+                .copied()
+                .unwrap_or(Binding::Global),
+        )
+    }
+
+    /// Records the resolved `locals`. Every other expression is global.
+    pub(crate) fn set_locals(&mut self, locals: Vec<(ExprId, Binding)>) {
+        let mut bindings = vec![Binding::Global; self.exprs.len()];
+        for (expr, binding) in locals {
+            bindings[expr.0 as usize] = binding;
+        }
+        self.bindings = Some(bindings);
+    }
+
+    /// All expressions in the program, in the order they were added.
+    pub fn exprs(&self) -> impl Iterator<Item = (ExprId, &Expr)> {
+        (0..).map(ExprId).zip(&self.exprs)
+    }
+}
+
+impl Index<ExprId> for Program {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+}
+
+impl IndexMut<ExprId> for Program {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.0 as usize]
+    }
+}
+
+/// Identifies an expression within its [`Program`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct ExprId(u32);
+
+/// Identifies a statement within its [`Program`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct StatementId(u32);
+
+/// Identifies any node of a [`Program`], for analyses whose side tables
+/// cover both expressions and statements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum NodeId {
+    Expr(ExprId),
+    Statement(StatementId),
+}
+
+impl From<ExprId> for NodeId {
+    fn from(id: ExprId) -> Self {
+        Self::Expr(id)
+    }
+}
+
+impl From<StatementId> for NodeId {
+    fn from(id: StatementId) -> Self {
+        Self::Statement(id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ExprKind {
+    Assign {
+        name: Name,
+        value: ExprId,
+    },
+    /// `(a, b) = value`: assigns the elements of a tuple to the variables
+    /// `targets`, each of them a [`Variable`](ExprKind::Variable).
+    AssignTuple {
+        targets: Vec<ExprId>,
+        value: ExprId,
+    },
+    Binary {
+        left: ExprId,
+        operator: BinaryOp,
+        right: ExprId,
+    },
+    Call {
+        callee: ExprId,
+        paren: Token,
+        arguments: Vec<ExprId>,
+    },
+    Get {
+        object: ExprId,
+        name: Name,
+    },
+    Grouping(ExprId),
+    Literal(Literal),
+    Logical {
+        left: ExprId,
+        operator: LogicalOp,
+        right: ExprId,
+    },
+    Set {
+        object: ExprId,
+        name: Name,
+        value: ExprId,
+    },
+    Super {
+        keyword: Name,
+        method: Name,
+    },
+    This(Name),
+    /// `(a, b)`, or `(a,)` for a tuple of one element.
+    Tuple(Vec<ExprId>),
+    Unary {
+        operator: UnaryOp,
+        right: ExprId,
+    },
+    Variable(Name),
+}
+
+/// A name in the source, such as that of a variable, property or method:
+/// the token it was written as, and its text interned once while parsing,
+/// so that running the program looks it up without interning it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+    pub token: Token,
+    pub symbol: Symbol,
+}
+
+impl Name {
+    pub fn new(token: Token) -> Self {
+        let symbol = Symbol::permanent(&token.lexeme);
+        Self { token, symbol }
+    }
+}
+
+/// Serializes as the token alone: the symbol only repeats its text.
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.token.serialize(serializer)
+    }
+}
+
+/// A literal's token, with the text of a string or char literal interned
+/// once while parsing, for the value it evaluates to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub token: Token,
+    pub string: Option<Symbol>,
+}
+
+impl Literal {
+    pub fn new(token: Token) -> Self {
+        let string = match &token.token_type {
+            TokenType::String(string) => Some(Symbol::permanent(string)),
+            TokenType::Char(c) => Some(Symbol::permanent(c.encode_utf8(&mut [0; 4]))),
+            _ => None,
+        };
+        Self { token, string }
+    }
+}
+
+/// Serializes as the token alone, which holds the string too.
+impl Serialize for Literal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.token.serialize(serializer)
+    }
+}
+
+impl ExprKind {
+    /// The operands of this expression.
+    pub fn children(&self) -> Vec<ExprId> {
+        match self {
+            Self::Assign { value, .. } => vec![*value],
+            Self::AssignTuple { targets, value } => {
+                targets.iter().chain([value]).copied().collect()
+            }
+            Self::Binary { left, right, .. } | Self::Logical { left, right, .. } => {
+                vec![*left, *right]
+            }
+            Self::Call {
+                callee, arguments, ..
+            } => std::iter::once(*callee)
+                .chain(arguments.iter().copied())
+                .collect(),
+            Self::Get { object, .. } => vec![*object],
+            Self::Grouping(expr) => vec![*expr],
+            Self::Set { object, value, .. } => vec![*object, *value],
+            Self::Tuple(elements) => elements.clone(),
+            Self::Unary { right, .. } => vec![*right],
+            Self::Literal(_) | Self::Super { .. } | Self::This(_) | Self::Variable(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BinaryOp {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Remainder => "%",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Not => write!(f, "!"),
+            Self::Negate => write!(f, "-"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+impl fmt::Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::And => write!(f, "and"),
+            Self::Or => write!(f, "or"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    pub id: StatementId,
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum StatementKind {
+    /// Fails with a runtime error if `condition` is falsey, the error
+    /// saying what `message`, if given, evaluates to.
+    Assert {
+        keyword: Token,
+        condition: ExprId,
+        message: Option<ExprId>,
+    },
+    Block(Vec<Statement>),
+    /// Leaves the innermost loop, or the loop named by `label`.
+    Break {
+        keyword: Token,
+        label: Option<Token>,
+    },
+    Class {
+        name: Name,
+        superclass: Option<ExprId>,
+        /// Fields each instance starts with, in the order they are set.
+        fields: Vec<Field>,
+        /// Shared with the methods the interpreter makes from them.
+        methods: Vec<Rc<Function>>,
+        doc: Option<String>,
+    },
+    /// Skips to the next iteration of the innermost loop, or of the loop
+    /// named by `label`.
+    Continue {
+        keyword: Token,
+        label: Option<Token>,
+    },
+    Expression(ExprId),
+    /// A `for` loop as written. [`lower`](crate::unstable::lower::lower)
+    /// turns it into a `while` loop, which is all the interpreter runs.
+    For {
+        initializer: Option<Box<Statement>>,
+        condition: Option<ExprId>,
+        increment: Option<ExprId>,
+        body: Box<Statement>,
+        label: Option<Token>,
+    },
+    /// Shared with the functions the interpreter makes from it.
+    Function(Rc<Function>),
+    If {
+        condition: ExprId,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+    },
+    Print(ExprId),
+    Return {
+        keyword: Token,
+        value: Option<ExprId>,
+    },
+    /// Raises `value` as an exception, which unwinds to the innermost
+    /// `try` statement with a `catch` clause.
+    Throw {
+        keyword: Token,
+        value: ExprId,
+    },
+    /// Runs `body`, a block. If it throws, or fails with a runtime error,
+    /// `catch` runs instead of the rest of it; `finally` runs last either
+    /// way. The parser requires at least one of the two clauses.
+    Try {
+        body: Box<Statement>,
+        catch: Option<Catch>,
+        finally: Option<Box<Statement>>,
+    },
+    Var {
+        name: Name,
+        initializer: Option<ExprId>,
+    },
+    /// `var (a, b) = initializer;`: declares a variable for each element
+    /// of a tuple.
+    VarTuple {
+        names: Vec<Name>,
+        initializer: ExprId,
+    },
+    While {
+        condition: ExprId,
+        body: Box<Statement>,
+        /// Evaluated after each run of the body, including runs cut short
+        /// by `continue`. Only lowered `for` loops have one.
+        increment: Option<ExprId>,
+        label: Option<Token>,
+    },
+}
+
+impl Statement {
+    /// The expressions that belong directly to this statement, not to the
+    /// statements nested in it. The initializers of a class's fields are
+    /// left out, as they run inside the class rather than where it is
+    /// declared.
+    pub fn exprs(&self) -> Vec<ExprId> {
+        match &self.kind {
+            StatementKind::Assert {
+                condition, message, ..
+            } => std::iter::once(condition).chain(message).copied().collect(),
+            StatementKind::Class { superclass, .. } => superclass.iter().copied().collect(),
+            StatementKind::Expression(expr) | StatementKind::Print(expr) => vec![*expr],
+            StatementKind::For {
+                condition,
+                increment,
+                ..
+            } => condition.iter().chain(increment).copied().collect(),
+            StatementKind::If { condition, .. } => vec![*condition],
+            StatementKind::While {
+                condition,
+                increment,
+                ..
+            } => std::iter::once(condition)
+                .chain(increment)
+                .copied()
+                .collect(),
+            StatementKind::Throw { value, .. } => vec![*value],
+            StatementKind::VarTuple { initializer, .. } => vec![*initializer],
+            StatementKind::Return { value: expr, .. }
+            | StatementKind::Var {
+                initializer: expr, ..
+            } => expr.iter().copied().collect(),
+            StatementKind::Block(_)
+            | StatementKind::Break { .. }
+            | StatementKind::Continue { .. }
+            | StatementKind::Function(_)
+            | StatementKind::Try { .. } => Vec::new(),
+        }
+    }
+
+    /// The statements directly nested in this one, including the bodies of
+    /// functions and methods it declares.
+    pub fn children(&self) -> Vec<&Statement> {
+        match &self.kind {
+            StatementKind::Block(statements) => statements.iter().collect(),
+            StatementKind::Class { methods, .. } => {
+                methods.iter().flat_map(|method| &method.body).collect()
+            }
+            StatementKind::For {
+                initializer, body, ..
+            } => initializer
+                .as_deref()
+                .into_iter()
+                .chain(std::iter::once(&**body))
+                .collect(),
+            StatementKind::Function(function) => function.body.iter().collect(),
+            StatementKind::If {
+                then_branch,
+                else_branch,
+                ..
+            } => std::iter::once(&**then_branch)
+                .chain(else_branch.as_deref())
+                .collect(),
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => std::iter::once(&**body)
+                .chain(catch.as_ref().map(|catch| &*catch.body))
+                .chain(finally.as_deref())
+                .collect(),
+            StatementKind::While { body, .. } => vec![body],
+            StatementKind::Assert { .. }
+            | StatementKind::Break { .. }
+            | StatementKind::Continue { .. }
+            | StatementKind::Expression(_)
+            | StatementKind::Print(_)
+            | StatementKind::Return { .. }
+            | StatementKind::Throw { .. }
+            | StatementKind::Var { .. }
+            | StatementKind::VarTuple { .. } => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Function {
+    pub name: Name,
+    pub params: Vec<Name>,
+    /// Whether the last parameter is a rest parameter, `...rest`, which
+    /// takes a list of the arguments left over after the others.
+    pub variadic: bool,
+    pub body: Vec<Statement>,
+    /// Documentation from the `///` comments preceding the declaration.
+    pub doc: Option<String>,
+    pub span: Span,
+}
+
+impl Function {
+    /// The parameters as written, with `...` in front of a rest parameter.
+    pub fn written_params(&self) -> Vec<String> {
+        let mut params: Vec<_> = self
+            .params
+            .iter()
+            .map(|param| param.token.lexeme.clone())
+            .collect();
+        if let Some(rest) = params.last_mut().filter(|_| self.variadic) {
+            rest.insert_str(0, "...");
+        }
+        params
+    }
+}
+
+/// A field declared in a class body, `name = initializer;`. The
+/// initializer runs for every new instance, before `init`, with `this`
+/// referring to the instance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Field {
+    pub name: Name,
+    pub initializer: ExprId,
+}
+
+/// The `catch` clause of a `try` statement.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Catch {
+    /// The variable that holds the exception while `body` runs.
+    pub name: Name,
+    /// A block.
+    pub body: Box<Statement>,
+}
+
+/// An ordinary `//` comment and the statement it belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comment {
+    /// The text after the `//`.
+    pub text: String,
+    pub span: Span,
+    /// `None` only when the program has no statements at all.
+    pub statement: Option<StatementId>,
+    pub placement: CommentPlacement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CommentPlacement {
+    /// On its own line(s) before the statement.
+    Leading,
+    /// After the statement, on the same line.
+    Trailing,
+    /// On its own line(s) after the statement, at the end of the enclosing
+    /// block or the program. A comment alone in an otherwise empty block
+    /// ends the statement that owns the block.
+    End,
+}
@@ -0,0 +1,261 @@
+//! Renders the AST in a fully parenthesized, Lisp-like form such as
+//! `(* (- 123) (group 45.67))`, which makes its structure explicit.
+
+use crate::{
+    ast::{ExprId, ExprKind, Function, Program, Statement, StatementKind},
+    scanner::Token,
+};
+
+pub fn print_expr(program: &Program, expr: ExprId) -> String {
+    match &program[expr].kind {
+        ExprKind::Assign { name, value } => parenthesize(
+            "=",
+            [name.token.lexeme.clone(), print_expr(program, *value)],
+        ),
+        ExprKind::AssignTuple { targets, value } => parenthesize(
+            "=",
+            [
+                parenthesize(
+                    "tuple",
+                    targets.iter().map(|expr| print_expr(program, *expr)),
+                ),
+                print_expr(program, *value),
+            ],
+        ),
+        ExprKind::Binary {
+            left,
+            operator,
+            right,
+        } => parenthesize(
+            operator,
+            [print_expr(program, *left), print_expr(program, *right)],
+        ),
+        ExprKind::Call {
+            callee, arguments, ..
+        } => parenthesize(
+            "call",
+            std::iter::once(print_expr(program, *callee))
+                .chain(arguments.iter().map(|expr| print_expr(program, *expr))),
+        ),
+        ExprKind::Get { object, name } => parenthesize(
+            ".",
+            [print_expr(program, *object), name.token.lexeme.clone()],
+        ),
+        ExprKind::Grouping(expr) => parenthesize("group", [print_expr(program, *expr)]),
+        ExprKind::Literal(literal) => literal.token.lexeme.clone(),
+        ExprKind::Logical {
+            left,
+            operator,
+            right,
+        } => parenthesize(
+            operator,
+            [print_expr(program, *left), print_expr(program, *right)],
+        ),
+        ExprKind::Set {
+            object,
+            name,
+            value,
+        } => parenthesize(
+            "=",
+            [
+                parenthesize(
+                    ".",
+                    [print_expr(program, *object), name.token.lexeme.clone()],
+                ),
+                print_expr(program, *value),
+            ],
+        ),
+        ExprKind::Super { method, .. } => parenthesize("super", [method.token.lexeme.clone()]),
+        ExprKind::This(_) => "this".to_owned(),
+        ExprKind::Tuple(elements) => parenthesize(
+            "tuple",
+            elements.iter().map(|expr| print_expr(program, *expr)),
+        ),
+        ExprKind::Unary { operator, right } => {
+            parenthesize(operator, [print_expr(program, *right)])
+        }
+        ExprKind::Variable(name) => name.token.lexeme.clone(),
+    }
+}
+
+pub fn print_statement(program: &Program, statement: &Statement) -> String {
+    match &statement.kind {
+        StatementKind::Assert {
+            condition, message, ..
+        } => parenthesize(
+            "assert",
+            std::iter::once(condition)
+                .chain(message)
+                .map(|expr| print_expr(program, *expr)),
+        ),
+        StatementKind::Block(statements) => parenthesize(
+            "block",
+            statements
+                .iter()
+                .map(|statement| print_statement(program, statement)),
+        ),
+        StatementKind::Class {
+            name,
+            superclass,
+            fields,
+            methods,
+            ..
+        } => {
+            let mut parts = vec![name.token.lexeme.clone()];
+            if let Some(superclass) = superclass {
+                parts.push(parenthesize("<", [print_expr(program, *superclass)]));
+            }
+            parts.extend(fields.iter().map(|field| {
+                parenthesize(
+                    "field",
+                    [
+                        field.name.token.lexeme.clone(),
+                        print_expr(program, field.initializer),
+                    ],
+                )
+            }));
+            parts.extend(
+                methods
+                    .iter()
+                    .map(|function| print_function(program, function)),
+            );
+            parenthesize("class", parts)
+        }
+        StatementKind::Break { label, .. } => {
+            parenthesize("break", label.iter().map(|label| label.lexeme.clone()))
+        }
+        StatementKind::Continue { label, .. } => {
+            parenthesize("continue", label.iter().map(|label| label.lexeme.clone()))
+        }
+        StatementKind::Expression(expr) => parenthesize(";", [print_expr(program, *expr)]),
+        StatementKind::For {
+            initializer,
+            condition,
+            increment,
+            body,
+            label,
+        } => {
+            // Missing clauses print as `_`, keeping the others in place.
+            let initializer = initializer.as_ref().map_or_else(
+                || "_".to_owned(),
+                |initializer| print_statement(program, initializer),
+            );
+            let clause = |expr: &Option<ExprId>| {
+                expr.map_or_else(|| "_".to_owned(), |expr| print_expr(program, expr))
+            };
+            let printed = parenthesize(
+                "for",
+                [
+                    initializer,
+                    clause(condition),
+                    clause(increment),
+                    print_statement(program, body),
+                ],
+            );
+            labeled(label.as_ref(), printed)
+        }
+        StatementKind::Function(function) => print_function(program, function),
+        StatementKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let mut parts = vec![
+                print_expr(program, *condition),
+                print_statement(program, then_branch),
+            ];
+            if let Some(else_branch) = else_branch {
+                parts.push(print_statement(program, else_branch));
+            }
+            parenthesize("if", parts)
+        }
+        StatementKind::Print(expr) => parenthesize("print", [print_expr(program, *expr)]),
+        StatementKind::Return { value, .. } => parenthesize(
+            "return",
+            value.iter().map(|expr| print_expr(program, *expr)),
+        ),
+        StatementKind::Throw { value, .. } => parenthesize("throw", [print_expr(program, *value)]),
+        StatementKind::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            let mut parts = vec![print_statement(program, body)];
+            if let Some(catch) = catch {
+                parts.push(parenthesize(
+                    "catch",
+                    [
+                        catch.name.token.lexeme.clone(),
+                        print_statement(program, &catch.body),
+                    ],
+                ));
+            }
+            if let Some(finally) = finally {
+                parts.push(parenthesize("finally", [print_statement(program, finally)]));
+            }
+            parenthesize("try", parts)
+        }
+        StatementKind::Var { name, initializer } => parenthesize(
+            "var",
+            std::iter::once(name.token.lexeme.clone())
+                .chain(initializer.iter().map(|expr| print_expr(program, *expr))),
+        ),
+        StatementKind::VarTuple { names, initializer } => parenthesize(
+            "var",
+            [
+                parenthesize("tuple", names.iter().map(|name| name.token.lexeme.clone())),
+                print_expr(program, *initializer),
+            ],
+        ),
+        StatementKind::While {
+            condition,
+            body,
+            increment,
+            label,
+        } => {
+            let printed = parenthesize(
+                "while",
+                [
+                    print_expr(program, *condition),
+                    print_statement(program, body),
+                ]
+                .into_iter()
+                .chain(increment.map(|increment| print_expr(program, increment))),
+            );
+            labeled(label.as_ref(), printed)
+        }
+    }
+}
+
+/// Wraps a printed loop in its label, as `(label: loop)`.
+fn labeled(label: Option<&Token>, printed: String) -> String {
+    match label {
+        Some(label) => parenthesize(format!("{}:", label.lexeme), [printed]),
+        None => printed,
+    }
+}
+
+fn print_function(program: &Program, function: &Function) -> String {
+    let params = function.written_params().join(" ");
+    parenthesize(
+        "fun",
+        [function.name.token.lexeme.clone(), format!("({params})")]
+            .into_iter()
+            .chain(
+                function
+                    .body
+                    .iter()
+                    .map(|statement| print_statement(program, statement)),
+            ),
+    )
+}
+
+fn parenthesize(name: impl ToString, parts: impl IntoIterator<Item = String>) -> String {
+    let mut output = format!("({}", name.to_string());
+    for part in parts {
+        output.push(' ');
+        output.push_str(&part);
+    }
+    output.push(')');
+    output
+}
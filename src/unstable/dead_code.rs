@@ -0,0 +1,287 @@
+//! Finds statements that can never run: those after a statement that
+//! always returns or jumps, and branches ruled out by a constant condition.
+
+use std::fmt;
+
+use crate::{
+    ast::{ExprId, ExprKind, Program, Statement, StatementKind, UnaryOp},
+    scanner::{Span, Token, TokenType},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeadCode {
+    /// Statements that follow, in the same block, one that always returns,
+    /// jumps out of a loop, or loops forever. The span covers all of them.
+    Unreachable { span: Span },
+    /// The branch of an `if`, or the body of a `while`, that the constant
+    /// condition in front of it rules out.
+    NeverTaken { span: Span },
+}
+
+impl DeadCode {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Unreachable { span } | Self::NeverTaken { span } => *span,
+        }
+    }
+}
+
+impl fmt::Display for DeadCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(f, "[line {}:{}] Warning: ", span.line, span.column)?;
+        match self {
+            Self::Unreachable { .. } => write!(f, "Unreachable code."),
+            Self::NeverTaken { .. } => write!(f, "Condition is constant; this never runs."),
+        }
+    }
+}
+
+/// Finds the dead code in `program`, in source order. Code inside dead
+/// code is not reported again.
+pub fn find_dead_code(program: &Program) -> Vec<DeadCode> {
+    let mut dead = Vec::new();
+    block(program, &program.statements, &mut dead);
+    dead
+}
+
+fn block(program: &Program, statements: &[Statement], dead: &mut Vec<DeadCode>) {
+    for (index, statement) in statements.iter().enumerate() {
+        statement_dead_code(program, statement, dead);
+        if exits(program, statement) {
+            if let [first, .., last] | [first @ last] = &statements[index + 1..] {
+                dead.push(DeadCode::Unreachable {
+                    span: first.span.to(last.span),
+                });
+            }
+            return;
+        }
+    }
+}
+
+fn statement_dead_code(program: &Program, statement: &Statement, dead: &mut Vec<DeadCode>) {
+    match &statement.kind {
+        StatementKind::Block(statements) => block(program, statements, dead),
+        StatementKind::Class { methods, .. } => {
+            for method in methods {
+                block(program, &method.body, dead);
+            }
+        }
+        StatementKind::Function(function) => block(program, &function.body, dead),
+        StatementKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let constant = truthiness(program, *condition);
+            if constant == Some(false) {
+                dead.push(DeadCode::NeverTaken {
+                    span: then_branch.span,
+                });
+            } else {
+                statement_dead_code(program, then_branch, dead);
+            }
+            if let Some(else_branch) = else_branch {
+                if constant == Some(true) {
+                    dead.push(DeadCode::NeverTaken {
+                        span: else_branch.span,
+                    });
+                } else {
+                    statement_dead_code(program, else_branch, dead);
+                }
+            }
+        }
+        StatementKind::For {
+            condition, body, ..
+        } if condition.is_some_and(|condition| truthiness(program, condition) == Some(false)) => {
+            dead.push(DeadCode::NeverTaken { span: body.span });
+        }
+        StatementKind::For { body, .. } => statement_dead_code(program, body, dead),
+        StatementKind::While {
+            condition, body, ..
+        } => {
+            if truthiness(program, *condition) == Some(false) {
+                dead.push(DeadCode::NeverTaken { span: body.span });
+            } else {
+                statement_dead_code(program, body, dead);
+            }
+        }
+        StatementKind::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            statement_dead_code(program, body, dead);
+            if let Some(catch) = catch {
+                statement_dead_code(program, &catch.body, dead);
+            }
+            if let Some(finally) = finally {
+                statement_dead_code(program, finally, dead);
+            }
+        }
+        StatementKind::Assert { .. }
+        | StatementKind::Break { .. }
+        | StatementKind::Continue { .. }
+        | StatementKind::Expression(_)
+        | StatementKind::Print(_)
+        | StatementKind::Return { .. }
+        | StatementKind::Throw { .. }
+        | StatementKind::Var { .. }
+        | StatementKind::VarTuple { .. } => {}
+    }
+}
+
+/// Whether running `statement` never carries on to the statement after it.
+fn exits(program: &Program, statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Break { .. }
+        | StatementKind::Continue { .. }
+        | StatementKind::Return { .. }
+        | StatementKind::Throw { .. } => true,
+        // A `finally` clause that exits takes over from whatever the rest
+        // of the statement did. Otherwise both the body and, if it throws,
+        // the `catch` clause must exit.
+        StatementKind::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            finally
+                .as_ref()
+                .is_some_and(|finally| exits(program, finally))
+                || exits(program, body)
+                    && catch
+                        .as_ref()
+                        .is_none_or(|catch| exits(program, &catch.body))
+        }
+        StatementKind::Block(statements) => {
+            statements.iter().any(|statement| exits(program, statement))
+        }
+        StatementKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let else_exits = else_branch
+                .as_ref()
+                .is_some_and(|else_branch| exits(program, else_branch));
+            match truthiness(program, *condition) {
+                Some(true) => exits(program, then_branch),
+                Some(false) => else_exits,
+                None => exits(program, then_branch) && else_exits,
+            }
+        }
+        // A loop whose condition stays true can only be left by returning
+        // or by a `break` aimed at it.
+        StatementKind::While {
+            condition,
+            body,
+            label,
+            ..
+        } => truthiness(program, *condition) == Some(true) && !breaks(body, label, false),
+        StatementKind::For {
+            condition,
+            body,
+            label,
+            ..
+        } => {
+            condition.map_or(Some(true), |condition| truthiness(program, condition)) == Some(true)
+                && !breaks(body, label, false)
+        }
+        StatementKind::Assert { .. }
+        | StatementKind::Class { .. }
+        | StatementKind::Expression(_)
+        | StatementKind::Function(_)
+        | StatementKind::Print(_)
+        | StatementKind::Var { .. }
+        | StatementKind::VarTuple { .. } => false,
+    }
+}
+
+/// Whether `statement`, in the body of the loop labeled `label`, holds a
+/// `break` that leaves that loop. `nested` is whether `statement` is also
+/// inside a loop within that body, which unlabeled `break`s leave instead.
+fn breaks(statement: &Statement, label: &Option<Token>, nested: bool) -> bool {
+    let targets = |other: &Option<Token>| matches!((label, other), (Some(label), Some(other)) if label.lexeme == other.lexeme);
+    match &statement.kind {
+        StatementKind::Break { label: target, .. } => match target {
+            Some(_) => targets(target),
+            None => !nested,
+        },
+        // A loop of the same name hides this one.
+        StatementKind::For { label: inner, .. } | StatementKind::While { label: inner, .. } => {
+            !targets(inner)
+                && statement
+                    .children()
+                    .into_iter()
+                    .any(|child| breaks(child, label, true))
+        }
+        StatementKind::Class { .. } | StatementKind::Function(_) => false,
+        _ => statement
+            .children()
+            .into_iter()
+            .any(|child| breaks(child, label, nested)),
+    }
+}
+
+/// Whether `expr` is always truthy or always falsey, if that is evident
+/// without evaluating anything.
+fn truthiness(program: &Program, expr: ExprId) -> Option<bool> {
+    match &program[expr].kind {
+        ExprKind::Literal(literal) => Some(!matches!(
+            literal.token.token_type,
+            TokenType::False | TokenType::Nil
+        )),
+        ExprKind::Grouping(expr) => truthiness(program, *expr),
+        ExprKind::Unary {
+            operator: UnaryOp::Not,
+            right,
+        } => truthiness(program, *right).map(|truthy| !truthy),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse, scanner::scan_tokens};
+
+    /// The dead code in `source`, as the CLI reports it.
+    fn dead_code(source: &str) -> Vec<String> {
+        let program = parse(scan_tokens(source.as_bytes()).unwrap()).unwrap();
+        let dead = find_dead_code(&program);
+        dead.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn finds_statements_after_an_exit() {
+        assert_eq!(
+            dead_code("fun f() {\n  return 1;\n  print 2;\n  print 3;\n}"),
+            ["[line 3:3] Warning: Unreachable code."]
+        );
+        assert_eq!(
+            dead_code("while (true) {} print 1;"),
+            ["[line 1:17] Warning: Unreachable code."]
+        );
+        assert_eq!(
+            dead_code("while (x) { break; print 1; } print 2;"),
+            ["[line 1:20] Warning: Unreachable code."]
+        );
+    }
+
+    #[test]
+    fn finds_branches_a_constant_condition_rules_out() {
+        assert_eq!(
+            dead_code("if (false) print 1; else print 2;\nwhile (nil) print 3;"),
+            [
+                "[line 1:12] Warning: Condition is constant; this never runs.",
+                "[line 2:13] Warning: Condition is constant; this never runs.",
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_code_that_may_run() {
+        assert!(dead_code("fun f() { if (x) return; print 1; }").is_empty());
+    }
+}
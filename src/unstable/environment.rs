@@ -0,0 +1,165 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    intern::Symbol,
+    interpreter::{Error, ErrorKind, Value},
+};
+
+/// Where a variable's value lives. Scopes keep their variables in cells,
+/// and a closure captures them by reference through the scope it was
+/// declared in: it sees what the scope assigns later, and the scope sees
+/// what it assigns. Clones refer to the same cell.
+#[derive(Debug, Clone)]
+pub struct Cell(pub(crate) Rc<RefCell<Value>>);
+
+impl Cell {
+    pub fn new(value: Value) -> Self {
+        Self(Rc::new(RefCell::new(value)))
+    }
+
+    pub fn get(&self) -> Value {
+        self.0.borrow().clone()
+    }
+
+    pub fn set(&self, value: Value) {
+        *self.0.borrow_mut() = value;
+    }
+
+    /// Whether anything besides the scope holds on to the cell, or its
+    /// value is being replaced. The garbage collector cannot account for
+    /// the references the value holds then.
+    pub(crate) fn in_use(&self) -> bool {
+        Rc::strong_count(&self.0) > 1 || self.0.try_borrow_mut().is_err()
+    }
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A scope of variable bindings, linked to the scope enclosing it.
+///
+/// A local scope keeps its variables in the order they were declared,
+/// which is the order the resolver numbered their slots in, so that a
+/// resolved reference indexes straight into it. Only the global scope,
+/// which encloses every other, finds its variables by name.
+#[derive(Debug, Default)]
+pub struct Environment {
+    slots: Vec<(Symbol, Cell)>,
+    globals: HashMap<Symbol, Cell>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            slots: Vec::new(),
+            globals: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// The cells of the variables in this scope alone.
+    pub(crate) fn cells(&self) -> impl Iterator<Item = &Cell> {
+        self.slots
+            .iter()
+            .map(|(_, cell)| cell)
+            .chain(self.globals.values())
+    }
+
+    pub(crate) fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.enclosing.as_ref()
+    }
+
+    /// Removes every variable from this scope, for the garbage collector
+    /// to break the cycles running through it.
+    pub(crate) fn clear(&mut self) {
+        self.slots.clear();
+        self.globals.clear();
+    }
+
+    /// Declares `name` in this scope, in a cell of its own. A variable
+    /// declared again gets a new cell, in the same slot, and closures that
+    /// captured the old one keep it.
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        if self.enclosing.is_none() {
+            self.globals.insert(name, Cell::new(value));
+        } else if let Some((_, cell)) = self.slots.iter_mut().find(|(slot, _)| *slot == name) {
+            *cell = Cell::new(value);
+        } else {
+            self.slots.push((name, Cell::new(value)));
+        }
+    }
+
+    /// The cell for `name` in this scope alone.
+    fn own_cell(&self, name: &Symbol) -> Option<&Cell> {
+        match self.enclosing {
+            Some(_) => self
+                .slots
+                .iter()
+                .find_map(|(slot, cell)| (slot == name).then_some(cell)),
+            None => self.globals.get(name),
+        }
+    }
+
+    /// The cell holding `name`, from this scope or the nearest enclosing
+    /// one that declares it.
+    pub fn cell(&self, name: &Symbol) -> Option<Cell> {
+        match (self.own_cell(name), &self.enclosing) {
+            (Some(cell), _) => Some(cell.clone()),
+            (None, Some(enclosing)) => enclosing.borrow().cell(name),
+            (None, None) => None,
+        }
+    }
+
+    pub fn get(&self, name: &Symbol) -> Result<Value, Error> {
+        match (self.own_cell(name), &self.enclosing) {
+            (Some(cell), _) => Ok(cell.get()),
+            (None, Some(enclosing)) => enclosing.borrow().get(name),
+            (None, None) => Err(ErrorKind::UndefinedVariable(name.to_string()).into()),
+        }
+    }
+
+    /// Calls `f` with the cell in `slot` of the local scope `distance`
+    /// levels out, as the resolver found the variable to be.
+    fn with_slot<R>(&self, distance: usize, slot: usize, f: impl FnOnce(&Cell) -> R) -> Option<R> {
+        match (distance, &self.enclosing) {
+            (0, _) => self.slots.get(slot).map(|(_, cell)| f(cell)),
+            (_, Some(enclosing)) => enclosing.borrow().with_slot(distance - 1, slot, f),
+            (_, None) => None,
+        }
+    }
+
+    /// Like [`get`](Self::get), but reads `slot` of the scope `distance`
+    /// levels out, as the resolver found the variable `name` to be.
+    pub fn get_at(&self, distance: usize, slot: usize, name: &Symbol) -> Result<Value, Error> {
+        self.with_slot(distance, slot, Cell::get)
+            .ok_or_else(|| ErrorKind::UndefinedVariable(name.to_string()).into())
+    }
+
+    pub fn assign(&mut self, name: &Symbol, value: Value) -> Result<(), Error> {
+        if let Some(cell) = self.own_cell(name) {
+            cell.set(value);
+            return Ok(());
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(ErrorKind::UndefinedVariable(name.to_string()).into()),
+        }
+    }
+
+    /// Like [`assign`](Self::assign), but only to `slot` of the scope
+    /// `distance` levels out.
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        slot: usize,
+        name: &Symbol,
+        value: Value,
+    ) -> Result<(), Error> {
+        self.with_slot(distance, slot, |cell| cell.set(value))
+            .ok_or_else(|| ErrorKind::UndefinedVariable(name.to_string()).into())
+    }
+}
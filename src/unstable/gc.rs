@@ -0,0 +1,310 @@
+//! A cycle collector for the objects scripts create. Scopes, closures,
+//! classes, instances, tuples and lists are reference counted, which frees most
+//! of them as soon as nothing uses them, but not those that refer to each
+//! other in a cycle: a function closing over the scope that holds it, say,
+//! or an instance stored in its own field.
+//!
+//! The [`Heap`] keeps a weak handle to every such object. To collect, it
+//! counts for each live object the references held by other objects on
+//! the heap. An object with more references than that is also held from
+//! outside: by the interpreter, by the Rust code running at the time, or
+//! by the embedding application. Everything reachable from those roots is
+//! marked, and the scopes and instances left unmarked are emptied, which
+//! breaks their cycles and lets reference counting free them.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    environment::{Cell, Environment},
+    interpreter::{Class, Closure, Instance, Value},
+};
+
+/// How many objects are allocated before the first collection. Later
+/// collections wait until the heap has doubled.
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Every scope, closure, class, instance, tuple and list an interpreter
+/// has created, for it to collect the cycles among them.
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<Handle>,
+    /// Objects allocated since the last collection.
+    allocated: usize,
+    threshold: usize,
+    /// Whether to collect before every allocation, which makes a missing
+    /// root show up at once rather than some time later.
+    pub(crate) stress: bool,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            allocated: 0,
+            threshold: INITIAL_THRESHOLD,
+            stress: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Handle {
+    Environment(Weak<RefCell<Environment>>),
+    Instance(Weak<RefCell<Instance>>),
+    Closure(Weak<Closure>),
+    Class(Weak<Class>),
+    Tuple(Weak<Box<[Value]>>),
+    List(Weak<RefCell<Vec<Value>>>),
+}
+
+impl Handle {
+    fn upgrade(&self) -> Option<Object> {
+        Some(match self {
+            Self::Environment(weak) => Object::Environment(weak.upgrade()?),
+            Self::Instance(weak) => Object::Instance(weak.upgrade()?),
+            Self::Closure(weak) => Object::Closure(weak.upgrade()?),
+            Self::Class(weak) => Object::Class(weak.upgrade()?),
+            Self::Tuple(weak) => Object::Tuple(weak.upgrade()?),
+            Self::List(weak) => Object::List(weak.upgrade()?),
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Environment(weak) => weak.strong_count() > 0,
+            Self::Instance(weak) => weak.strong_count() > 0,
+            Self::Closure(weak) => weak.strong_count() > 0,
+            Self::Class(weak) => weak.strong_count() > 0,
+            Self::Tuple(weak) => weak.strong_count() > 0,
+            Self::List(weak) => weak.strong_count() > 0,
+        }
+    }
+}
+
+enum Object {
+    Environment(Rc<RefCell<Environment>>),
+    Instance(Rc<RefCell<Instance>>),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Tuple(Rc<Box<[Value]>>),
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+/// Identifies an object on the heap by where it lives.
+type Address = *const ();
+
+impl Object {
+    fn address(&self) -> Address {
+        match self {
+            Self::Environment(object) => Rc::as_ptr(object).cast(),
+            Self::Instance(object) => Rc::as_ptr(object).cast(),
+            Self::Closure(object) => Rc::as_ptr(object).cast(),
+            Self::Class(object) => Rc::as_ptr(object).cast(),
+            Self::Tuple(object) => Rc::as_ptr(object).cast(),
+            Self::List(object) => Rc::as_ptr(object).cast(),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Self::Environment(object) => Rc::strong_count(object),
+            Self::Instance(object) => Rc::strong_count(object),
+            Self::Closure(object) => Rc::strong_count(object),
+            Self::Class(object) => Rc::strong_count(object),
+            Self::Tuple(object) => Rc::strong_count(object),
+            Self::List(object) => Rc::strong_count(object),
+        }
+    }
+
+    /// Calls `f` with the address of each object this one refers to, once
+    /// per reference. Returns `false`, having called `f` for nothing, if
+    /// the object is borrowed mutably and cannot be looked at, or is a
+    /// scope with a variable whose cell is held elsewhere as well.
+    fn references(&self, mut f: impl FnMut(Address)) -> bool {
+        match self {
+            Self::Environment(environment) => {
+                let Ok(environment) = environment.try_borrow() else {
+                    return false;
+                };
+                if environment.cells().any(Cell::in_use) {
+                    return false;
+                }
+                for cell in environment.cells() {
+                    address(&cell.0.borrow()).into_iter().for_each(&mut f);
+                }
+                if let Some(enclosing) = environment.enclosing() {
+                    f(Rc::as_ptr(enclosing).cast());
+                }
+            }
+            Self::Instance(instance) => {
+                let Ok(instance) = instance.try_borrow() else {
+                    return false;
+                };
+                f(Rc::as_ptr(&instance.class.0).cast());
+                instance
+                    .fields
+                    .values()
+                    .filter_map(address)
+                    .for_each(&mut f);
+            }
+            Self::Closure(closure) => f(Rc::as_ptr(&closure.closure).cast()),
+            Self::Class(class) => {
+                let Ok(fields) = class.fields.try_borrow() else {
+                    return false;
+                };
+                if let Some(superclass) = &class.superclass {
+                    f(Rc::as_ptr(&superclass.0).cast());
+                }
+                for method in class.methods.values() {
+                    f(Rc::as_ptr(&method.0).cast());
+                }
+                if let Some(initializers) = &class.initializers {
+                    f(Rc::as_ptr(&initializers.closure).cast());
+                }
+                fields.values().filter_map(address).for_each(&mut f);
+            }
+            Self::Tuple(elements) => elements.iter().filter_map(address).for_each(f),
+            Self::List(list) => {
+                let Ok(elements) = list.try_borrow() else {
+                    return false;
+                };
+                elements.iter().filter_map(address).for_each(f);
+            }
+        }
+        true
+    }
+
+    /// Drops the references this object holds that can be part of a
+    /// cycle. Closures and tuples cannot change once made, and classes
+    /// only in their fields, so every cycle runs through a scope, the
+    /// fields of an instance or a class, or a list.
+    fn clear(&self) {
+        match self {
+            Self::Environment(environment) => environment.borrow_mut().clear(),
+            Self::Instance(instance) => instance.borrow_mut().fields.clear(),
+            Self::List(list) => list.borrow_mut().clear(),
+            Self::Class(class) => class.fields.borrow_mut().clear(),
+            Self::Closure(_) | Self::Tuple(_) => {}
+        }
+    }
+}
+
+/// The address of the heap object `value` refers to, if it refers to one.
+fn address(value: &Value) -> Option<Address> {
+    match value {
+        Value::Function(function) => Some(Rc::as_ptr(&function.0).cast()),
+        Value::Class(class) => Some(Rc::as_ptr(&class.0).cast()),
+        Value::Instance(instance) => Some(Rc::as_ptr(&instance.0).cast()),
+        Value::Tuple(tuple) => Some(Rc::as_ptr(&tuple.0).cast()),
+        Value::List(list) => Some(Rc::as_ptr(&list.0).cast()),
+        // Native functions and host values may hold on to objects too,
+        // but cannot be looked into. Objects they hold count as held from
+        // outside, so they are kept.
+        Value::Nil
+        | Value::Boolean(_)
+        | Value::Number(_)
+        | Value::String(_)
+        | Value::Host(_)
+        | Value::Native(_) => None,
+    }
+}
+
+impl Heap {
+    pub(crate) fn environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(Handle::Environment(Rc::downgrade(&environment)));
+        environment
+    }
+
+    pub(crate) fn instance(&mut self, instance: Instance) -> Rc<RefCell<Instance>> {
+        let instance = Rc::new(RefCell::new(instance));
+        self.track(Handle::Instance(Rc::downgrade(&instance)));
+        instance
+    }
+
+    pub(crate) fn closure(&mut self, closure: Closure) -> Rc<Closure> {
+        let closure = Rc::new(closure);
+        self.track(Handle::Closure(Rc::downgrade(&closure)));
+        closure
+    }
+
+    pub(crate) fn class(&mut self, class: Class) -> Rc<Class> {
+        let class = Rc::new(class);
+        self.track(Handle::Class(Rc::downgrade(&class)));
+        class
+    }
+
+    pub(crate) fn tuple(&mut self, elements: Vec<Value>) -> Rc<Box<[Value]>> {
+        let tuple = Rc::new(elements.into_boxed_slice());
+        self.track(Handle::Tuple(Rc::downgrade(&tuple)));
+        tuple
+    }
+
+    pub(crate) fn list(&mut self, elements: Vec<Value>) -> Rc<RefCell<Vec<Value>>> {
+        let list = Rc::new(RefCell::new(elements));
+        self.track(Handle::List(Rc::downgrade(&list)));
+        list
+    }
+
+    fn track(&mut self, handle: Handle) {
+        if self.stress || self.allocated >= self.threshold {
+            self.collect();
+        }
+        self.allocated += 1;
+        self.objects.push(handle);
+    }
+
+    /// Frees the objects that only cycles among themselves keep alive.
+    pub fn collect(&mut self) {
+        let objects: Vec<Object> = self.objects.iter().filter_map(Handle::upgrade).collect();
+        let index: HashMap<Address, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| (object.address(), index))
+            .collect();
+
+        let mut internal = vec![0; objects.len()];
+        let mut marked = vec![false; objects.len()];
+        for (object, marked) in objects.iter().zip(&mut marked) {
+            // An object in use cannot be looked into, so it is a root, and
+            // the references it holds count as held from outside.
+            *marked = !object.references(|address| {
+                if let Some(&referent) = index.get(&address) {
+                    internal[referent] += 1;
+                }
+            });
+        }
+        // `objects` holds one reference to each object itself.
+        let mut pending = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            if marked[index] || object.strong_count() - 1 > internal[index] {
+                marked[index] = true;
+                pending.push(index);
+            }
+        }
+        while let Some(object) = pending.pop() {
+            objects[object].references(|address| {
+                if let Some(&referent) = index.get(&address) {
+                    if !marked[referent] {
+                        marked[referent] = true;
+                        pending.push(referent);
+                    }
+                }
+            });
+        }
+
+        for (object, marked) in objects.iter().zip(marked) {
+            if !marked {
+                object.clear();
+            }
+        }
+        drop(objects);
+        self.objects.retain(Handle::is_alive);
+        self.allocated = 0;
+        self.threshold = INITIAL_THRESHOLD.max(2 * self.objects.len());
+    }
+}
@@ -0,0 +1,403 @@
+//! Re-parsing after an edit, for editors that keep a program up to date as
+//! the user types. Only the top-level statements the edit touches are
+//! scanned and parsed again; the rest are kept, their positions moved to
+//! where the edit left them.
+
+use std::{error, fmt, ops::Range, rc::Rc};
+
+use crate::{
+    api::CompileError,
+    ast::{ExprId, ExprKind, Function, Program, Statement, StatementKind},
+    parser,
+    scanner::{self, Span, Token},
+};
+
+/// A change to a source file: the bytes in `range` were replaced by
+/// `new_len` bytes of new text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub new_len: usize,
+}
+
+#[derive(Debug)]
+pub enum ReparseError {
+    /// The edit reaches past the end of the old source, or what it leaves
+    /// of it reaches past the end of the new one.
+    EditOutOfRange,
+    Compile(CompileError),
+}
+
+impl fmt::Display for ReparseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EditOutOfRange => write!(f, "The edit does not fit the source."),
+            Self::Compile(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl error::Error for ReparseError {}
+
+/// Updates `program`, parsed by [`parse`](crate::parser::parse) from the
+/// source before `edit`, to match `source`, the text after it. Returns the
+/// indices in `program.statements` of the statements parsed anew.
+///
+/// If the edited region does not parse, or the edit does not fit the
+/// program and `source`, an error is returned and `program` still
+/// describes the old source. Expressions of replaced
+/// statements stay in the arena, so parse from scratch now and then to
+/// reclaim them.
+pub fn reparse(
+    program: &mut Program,
+    source: &[u8],
+    edit: &Edit,
+) -> Result<Range<usize>, ReparseError> {
+    let statements = &program.statements;
+    let bytes = edit.new_len as isize - edit.range.len() as isize;
+    // The old source ran at least to the end of the edit and of the last
+    // statement; that end, moved by the edit, must be in the new source.
+    let old_end = statements
+        .iter()
+        .map(|statement| statement.span.end)
+        .chain(program.result.map(|result| program[result].span.end))
+        .fold(edit.range.end, usize::max);
+    let fits = old_end
+        .checked_add_signed(bytes)
+        .is_some_and(|new_end| new_end <= source.len());
+    if edit.range.start > edit.range.end || !fits {
+        return Err(ReparseError::EditOutOfRange);
+    }
+    let first = statements.partition_point(|statement| statement.span.end < edit.range.start);
+    // The statement after the edit is parsed again as well: the edit may
+    // have changed the doc comment in front of it.
+    let last = statements
+        .partition_point(|statement| statement.span.start <= edit.range.end)
+        .saturating_add(1)
+        .min(statements.len());
+    let start = match first {
+        0 => 0,
+        first => statements[first - 1].span.end,
+    };
+    let kept = statements.get(last).map(|next| next.span);
+    // In range, as the statement ends before `old_end`.
+    let end = kept.map_or(source.len(), |next| next.start.wrapping_add_signed(bytes));
+
+    let tokens = scanner::scan_range(source, start..end)
+        .map_err(|errors| ReparseError::Compile(CompileError::Scan(errors)))?;
+    // Where the re-scanned region now ends, which is where the first kept
+    // statement now starts.
+    let moved = tokens.last().expect("scanner output ends with Eof").span;
+    // Only a region that runs to the end of the script can hold its result.
+    let (parsed, result) = parser::parse_statements(tokens, program, kept.is_none())
+        .map_err(|errors| ReparseError::Compile(CompileError::Parse(errors)))?;
+
+    let mut statements = std::mem::take(&mut program.statements);
+    if let Some(next) = kept {
+        let shift = Shift {
+            bytes,
+            lines: moved.line as isize - next.line as isize,
+            line: next.line,
+            columns: moved.column as isize - next.column as isize,
+        };
+        for statement in &mut statements[last..] {
+            shift.statement(program, statement);
+        }
+        if let Some(result) = program.result {
+            shift.expr(program, result);
+        }
+    } else {
+        program.result = result;
+    }
+    let count = parsed.len();
+    statements.splice(first..last, parsed);
+    program.statements = statements;
+    Ok(first..first + count)
+}
+
+/// Moves spans that follow an edit.
+struct Shift {
+    bytes: isize,
+    lines: isize,
+    /// The line on which the edit ended, in the old source. Only spans
+    /// starting on it move sideways.
+    line: usize,
+    columns: isize,
+}
+
+impl Shift {
+    fn span(&self, span: &mut Span) {
+        if span.line == self.line {
+            span.column = span.column.saturating_add_signed(self.columns);
+        }
+        span.line = span.line.saturating_add_signed(self.lines);
+        span.start = span.start.saturating_add_signed(self.bytes);
+        span.end = span.end.saturating_add_signed(self.bytes);
+    }
+
+    fn token(&self, token: &mut Token) {
+        self.span(&mut token.span);
+    }
+
+    fn statement(&self, program: &mut Program, statement: &mut Statement) {
+        self.span(&mut statement.span);
+        for expr in statement.exprs() {
+            self.expr(program, expr);
+        }
+        match &mut statement.kind {
+            StatementKind::Block(statements) => {
+                for statement in statements {
+                    self.statement(program, statement);
+                }
+            }
+            StatementKind::Class {
+                name,
+                fields,
+                methods,
+                ..
+            } => {
+                self.token(&mut name.token);
+                for field in fields {
+                    self.token(&mut field.name.token);
+                    self.expr(program, field.initializer);
+                }
+                for method in methods {
+                    self.function(program, Rc::make_mut(method));
+                }
+            }
+            StatementKind::Break { keyword, label }
+            | StatementKind::Continue { keyword, label } => {
+                self.token(keyword);
+                if let Some(label) = label {
+                    self.token(label);
+                }
+            }
+            StatementKind::For {
+                initializer,
+                body,
+                label,
+                ..
+            } => {
+                if let Some(label) = label {
+                    self.token(label);
+                }
+                if let Some(initializer) = initializer {
+                    self.statement(program, initializer);
+                }
+                self.statement(program, body);
+            }
+            StatementKind::Function(function) => self.function(program, Rc::make_mut(function)),
+            StatementKind::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.statement(program, then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(program, else_branch);
+                }
+            }
+            StatementKind::Assert { keyword, .. }
+            | StatementKind::Return { keyword, .. }
+            | StatementKind::Throw { keyword, .. } => {
+                self.token(keyword);
+            }
+            StatementKind::Var { name, .. } => {
+                self.token(&mut name.token);
+            }
+            StatementKind::VarTuple { names, .. } => {
+                for name in names {
+                    self.token(&mut name.token);
+                }
+            }
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.statement(program, body);
+                if let Some(catch) = catch {
+                    self.token(&mut catch.name.token);
+                    self.statement(program, &mut catch.body);
+                }
+                if let Some(finally) = finally {
+                    self.statement(program, finally);
+                }
+            }
+            StatementKind::While { body, label, .. } => {
+                if let Some(label) = label {
+                    self.token(label);
+                }
+                self.statement(program, body);
+            }
+            StatementKind::Expression(_) | StatementKind::Print(_) => {}
+        }
+    }
+
+    fn function(&self, program: &mut Program, function: &mut Function) {
+        self.span(&mut function.span);
+        self.token(&mut function.name.token);
+        for param in &mut function.params {
+            self.token(&mut param.token);
+        }
+        for statement in &mut function.body {
+            self.statement(program, statement);
+        }
+    }
+
+    fn expr(&self, program: &mut Program, id: ExprId) {
+        let expr = &mut program[id];
+        self.span(&mut expr.span);
+        match &mut expr.kind {
+            ExprKind::Assign { name, .. }
+            | ExprKind::Get { name, .. }
+            | ExprKind::Set { name, .. }
+            | ExprKind::This(name)
+            | ExprKind::Variable(name) => self.token(&mut name.token),
+            ExprKind::Call { paren, .. } => self.token(paren),
+            ExprKind::Literal(literal) => self.token(&mut literal.token),
+            ExprKind::Super { keyword, method } => {
+                self.token(&mut keyword.token);
+                self.token(&mut method.token);
+            }
+            ExprKind::AssignTuple { .. }
+            | ExprKind::Binary { .. }
+            | ExprKind::Grouping(_)
+            | ExprKind::Logical { .. }
+            | ExprKind::Tuple(_)
+            | ExprKind::Unary { .. } => {}
+        }
+        for child in expr.kind.children() {
+            self.expr(program, child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unstable::ast_printer::print_statement;
+
+    fn parse(source: &str) -> Program {
+        parser::parse(scanner::scan_tokens(source.as_bytes()).unwrap()).unwrap()
+    }
+
+    /// Each top-level statement as an s-expression, with the spans of the
+    /// statements and expressions in it, to compare programs whose
+    /// expression arenas differ.
+    fn layout(program: &Program) -> Vec<(String, Vec<Span>)> {
+        program
+            .statements
+            .iter()
+            .map(|statement| {
+                let mut spans = Vec::new();
+                statement_spans(program, statement, &mut spans);
+                (print_statement(program, statement), spans)
+            })
+            .collect()
+    }
+
+    fn statement_spans(program: &Program, statement: &Statement, spans: &mut Vec<Span>) {
+        spans.push(statement.span);
+        for expr in statement.exprs() {
+            expr_spans(program, expr, spans);
+        }
+        for child in statement.children() {
+            statement_spans(program, child, spans);
+        }
+    }
+
+    fn expr_spans(program: &Program, expr: ExprId, spans: &mut Vec<Span>) {
+        spans.push(program[expr].span);
+        for child in program[expr].kind.children() {
+            expr_spans(program, child, spans);
+        }
+    }
+
+    /// Applies the edit replacing the first `from` in `old` with `to` both
+    /// ways: incrementally and by parsing the new source from scratch.
+    fn check(old: &str, from: &str, to: &str) {
+        let start = old.find(from).unwrap();
+        check_range(old, start..start + from.len(), to);
+    }
+
+    fn check_range(old: &str, range: Range<usize>, text: &str) {
+        let new = format!("{}{text}{}", &old[..range.start], &old[range.end..]);
+        let mut program = parse(old);
+        let edit = Edit {
+            range,
+            new_len: text.len(),
+        };
+        reparse(&mut program, new.as_bytes(), &edit).unwrap();
+        assert_eq!(
+            layout(&program),
+            layout(&parse(&new)),
+            "after editing to {new:?}"
+        );
+    }
+
+    #[test]
+    fn matches_a_full_parse() {
+        let old = "var a = 1;\nfun f(x) {\n  return x + a;\n}\nprint f(2);\n";
+        // Inside one statement, growing and shrinking it.
+        check(old, "1", "100");
+        check(old, "x + a", "x");
+        check(old, "f(2)", "f(\n  2\n)");
+        // Across statements, joining and splitting lines.
+        check(old, "1;\nfun f(x) {\n", "1; fun f(x) { var y = 2;\n");
+        check(old, "}\nprint", "} print");
+        check(old, "a;\n}", "a;\n}\nvar b;");
+        // At the very start and the very end.
+        check_range(old, 0..0, "var b = 2;\n");
+        check_range(old, old.len()..old.len(), "print a;");
+        // Moving what follows on the same line.
+        check("print 1; print 2; print 3;", "1", "(1 +\n 10)");
+        // Replacing everything.
+        check_range(old, 0..old.len(), "class A {}");
+    }
+
+    #[test]
+    fn edits_that_do_not_fit_are_errors() {
+        let old = "var a = 1;\nprint a;";
+        let mut program = parse(old);
+        let before = layout(&program);
+        let out_of_range = [
+            // Past the end of both sources.
+            Edit {
+                range: 15..40,
+                new_len: 10,
+            },
+            // Longer than the new source.
+            Edit {
+                range: 0..0,
+                new_len: 5,
+            },
+            Edit {
+                range: Range { start: 4, end: 2 },
+                new_len: 0,
+            },
+        ];
+        for edit in out_of_range {
+            let error = reparse(&mut program, old.as_bytes(), &edit).unwrap_err();
+            assert!(matches!(error, ReparseError::EditOutOfRange), "{edit:?}");
+        }
+        assert_eq!(layout(&program), before);
+    }
+
+    #[test]
+    fn errors_leave_the_program_as_it_was() {
+        let old = "var a = 1;\nprint a;";
+        let mut program = parse(old);
+        let before = layout(&program);
+        let edit = Edit {
+            range: 8..9,
+            new_len: 1,
+        };
+        let error = reparse(&mut program, b"var a = );\nprint a;", &edit).unwrap_err();
+        assert!(matches!(
+            error,
+            ReparseError::Compile(CompileError::Parse(_))
+        ));
+        assert_eq!(layout(&program), before);
+    }
+}
@@ -0,0 +1,143 @@
+//! Interned strings. All live [`Symbol`]s with the same text share one
+//! allocation, so symbols compare and hash by address, and interning text
+//! that is already live allocates nothing.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::{Rc, Weak},
+};
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::default();
+}
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<Box<str>, Entry>,
+    /// Symbols kept for as long as the thread runs: names and literals
+    /// from source code, which a program interns over and over.
+    permanent: Vec<Rc<Box<str>>>,
+}
+
+struct Entry {
+    symbol: Weak<Box<str>>,
+    permanent: bool,
+}
+
+impl Interner {
+    fn intern(&mut self, text: &str, permanent: bool) -> Rc<Box<str>> {
+        let live = self
+            .symbols
+            .get_mut(text)
+            .and_then(|entry| Some((entry.symbol.upgrade()?, entry)));
+        let symbol = match live {
+            Some((symbol, entry)) if !permanent || entry.permanent => return symbol,
+            Some((symbol, entry)) => {
+                entry.permanent = true;
+                symbol
+            }
+            None => {
+                let symbol = Rc::new(Box::from(text));
+                let entry = Entry {
+                    symbol: Rc::downgrade(&symbol),
+                    permanent,
+                };
+                self.symbols.insert(text.into(), entry);
+                symbol
+            }
+        };
+        if permanent {
+            self.permanent.push(Rc::clone(&symbol));
+        }
+        symbol
+    }
+}
+
+/// An interned string. Clones share the text, and so does every symbol
+/// interned from the same text while one of them is alive.
+///
+/// The text sits behind a second pointer so that a symbol is one word,
+/// like the other payloads of a [`Value`](crate::interpreter::Value).
+pub struct Symbol(Rc<Box<str>>);
+
+impl Symbol {
+    /// Interns `text`. The text is freed once the last symbol for it is
+    /// dropped.
+    pub fn new(text: &str) -> Self {
+        Self(INTERNER.with(|interner| interner.borrow_mut().intern(text, false)))
+    }
+
+    /// Interns `text` for good, so that interning it again never
+    /// allocates. Meant for text taken from source code, of which there
+    /// is only so much.
+    pub fn permanent(text: &str) -> Self {
+        Self(INTERNER.with(|interner| interner.borrow_mut().intern(text, true)))
+    }
+}
+
+impl Clone for Symbol {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl Drop for Symbol {
+    fn drop(&mut self) {
+        // Permanent symbols are also held by the interner, so only others
+        // can be the last of their text.
+        if Rc::strong_count(&self.0) == 1 {
+            // The interner may already be gone if the thread is exiting.
+            let _ = INTERNER.try_with(|interner| interner.borrow_mut().symbols.remove(&**self.0));
+        }
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(text: String) -> Self {
+        Self::new(&text)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", &**self.0)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,2906 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::HashMap,
+    error, fmt,
+    io::{self, BufRead, Write},
+    rc::Rc,
+    slice,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ast::{
+        BinaryOp, Binding, ExprId, ExprKind, Field, Function, LogicalOp, Program, Statement,
+        StatementKind, UnaryOp,
+    },
+    environment::{Cell, Environment},
+    gc::Heap,
+    intern::Symbol,
+    scanner::{Span, TokenType},
+    unstable::stack,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Symbol),
+    /// A value belonging to the embedding application. Scripts can pass it
+    /// around and compare it, but operators only work on it as an
+    /// [`Operators`] hook defines.
+    Host(HostValue),
+    Native(NativeFunction),
+    Function(LoxFunction),
+    Class(LoxClass),
+    Instance(LoxInstance),
+    Tuple(LoxTuple),
+    List(LoxList),
+}
+
+// Values are cloned on every variable access and call, so each is kept to
+// a tag and a number or a single pointer, and cloning one at most bumps a
+// reference count.
+const _: () = assert!(std::mem::size_of::<Value>() <= 16);
+
+/// Formats values the way `print` shows them, as jlox does: strings
+/// without quotes, and whole numbers without a fractional part.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nil => write!(f, "nil"),
+            Self::Boolean(boolean) => write!(f, "{boolean}"),
+            Self::Number(number) => write_number(f, *number),
+            Self::String(string) => write!(f, "{string}"),
+            Self::Host(_) => write!(f, "<host value>"),
+            Self::Native(_) => write!(f, "<native fn>"),
+            Self::Function(function) => write!(f, "<fn {}>", function.0.declaration.name.symbol),
+            Self::Class(class) => write!(f, "{}", class.0.name),
+            Self::Instance(instance) => write!(f, "{} instance", instance.class().0.name),
+            Self::Tuple(tuple) => match tuple.elements() {
+                [element] => write!(f, "({element},)"),
+                elements => write_elements(f, elements, "(", ")"),
+            },
+            Self::List(list) => {
+                let address = Rc::as_ptr(&list.0).cast();
+                // A list may hold itself, directly or not.
+                let Some(_printing) = Printing::start(address) else {
+                    return write!(f, "[...]");
+                };
+                write_elements(f, &list.0.borrow(), "[", "]")
+            }
+        }
+    }
+}
+
+/// Writes `elements`, separated by commas, between `open` and `close`.
+fn write_elements(
+    f: &mut fmt::Formatter<'_>,
+    elements: &[Value],
+    open: &str,
+    close: &str,
+) -> fmt::Result {
+    write!(f, "{open}")?;
+    for (index, element) in elements.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{element}")?;
+    }
+    write!(f, "{close}")
+}
+
+thread_local! {
+    /// The lists being printed, by address.
+    static PRINTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a list as being printed until dropped.
+struct Printing;
+
+impl Printing {
+    /// Marks the list at `address`, unless it is being printed already.
+    fn start(address: *const ()) -> Option<Self> {
+        PRINTING.with_borrow_mut(|printing| {
+            if printing.contains(&address) {
+                return None;
+            }
+            printing.push(address);
+            Some(Self)
+        })
+    }
+}
+
+impl Drop for Printing {
+    fn drop(&mut self) {
+        PRINTING.with_borrow_mut(|printing| printing.pop());
+    }
+}
+
+/// Writes `number` as jlox prints it: as Java's `Double.toString` would,
+/// but without the `.0` of whole numbers.
+fn write_number(f: &mut fmt::Formatter<'_>, number: f64) -> fmt::Result {
+    if number.is_nan() {
+        return write!(f, "NaN");
+    }
+    if number.is_infinite() {
+        let sign = if number < 0.0 { "-" } else { "" };
+        return write!(f, "{sign}Infinity");
+    }
+    let magnitude = number.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        // Both Rust and Java give the shortest digits that read back as
+        // the same number, and Rust already leaves `.0` off whole numbers.
+        return write!(f, "{number}");
+    }
+    // Outside that range Java uses scientific notation, with at least one
+    // digit after the point: `1.0E7`, `1.5E-5`.
+    let scientific = format!("{number:e}");
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("scientific notation has an exponent");
+    let point = if mantissa.contains('.') { "" } else { ".0" };
+    write!(f, "{mantissa}{point}E{exponent}")
+}
+
+/// A shared handle to a value of any Rust type. Clones refer to the same
+/// value, and handles are equal only if they do.
+#[derive(Clone)]
+pub struct HostValue(Rc<Box<dyn Any>>);
+
+impl HostValue {
+    pub fn new<T: Any>(value: T) -> Self {
+        Self(Rc::new(Box::new(value)))
+    }
+
+    /// The value, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        (**self.0).downcast_ref()
+    }
+}
+
+impl PartialEq for HostValue {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for HostValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HostValue({:p})", Rc::as_ptr(&self.0))
+    }
+}
+
+/// A function written in Rust that scripts call like any other.
+/// Clones refer to the same function, and functions are equal only if
+/// they are clones.
+#[derive(Clone)]
+pub struct NativeFunction(Rc<Native>);
+
+struct Native {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+/// The Rust side of a [`NativeFunction`]: it gets the interpreter calling
+/// it and the arguments, and returns the call's value.
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, Error>;
+
+impl NativeFunction {
+    pub fn new(
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, Error> + 'static,
+    ) -> Self {
+        Self(Rc::new(Native {
+            name: name.into(),
+            arity,
+            function: Box::new(function),
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.0.arity
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error> {
+        (self.0.function)(interpreter, arguments)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.0.name)
+    }
+}
+
+/// A function declared in Lox, along with the scope it was declared in.
+/// Clones refer to the same function.
+#[derive(Clone)]
+pub struct LoxFunction(pub(crate) Rc<Closure>);
+
+pub(crate) struct Closure {
+    declaration: Rc<Function>,
+    /// The program the declaration came from, which holds the expressions
+    /// of its body.
+    program: Rc<Program>,
+    pub(crate) closure: Rc<RefCell<Environment>>,
+    /// Whether this is a class's `init` method, which returns the instance
+    /// rather than a value of its own.
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    /// This method, with `this` referring to `instance`.
+    fn bind(&self, instance: LoxInstance, heap: &mut Heap) -> Self {
+        let mut environment = Environment::new(Rc::clone(&self.0.closure));
+        environment.define(Symbol::permanent("this"), Value::Instance(instance));
+        let closure = Closure {
+            declaration: Rc::clone(&self.0.declaration),
+            program: Rc::clone(&self.0.program),
+            closure: heap.environment(environment),
+            is_initializer: self.0.is_initializer,
+        };
+        Self(heap.closure(closure))
+    }
+}
+
+impl Callable for LoxFunction {
+    /// A rest parameter is left out: it may take no arguments at all.
+    fn arity(&self) -> usize {
+        let declaration = &self.0.declaration;
+        declaration.params.len() - usize::from(declaration.variadic)
+    }
+
+    fn variadic(&self) -> bool {
+        self.0.declaration.variadic
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error> {
+        let (mut function, mut arguments) = match self.run(interpreter, arguments)? {
+            Flow::TailCall(function, arguments) => (function, arguments),
+            flow => return self.value(flow),
+        };
+        // Each call in tail position takes the place of the one that made
+        // it, so recursion through tail calls runs in constant stack space.
+        loop {
+            match function.run(interpreter, &arguments)? {
+                Flow::TailCall(callee, tail_arguments) => {
+                    // The call replaces the caller's frame as well.
+                    if let Some(call) = interpreter.calls.last_mut() {
+                        call.callee = Value::Function(callee.clone());
+                    }
+                    (function, arguments) = (callee, tail_arguments);
+                }
+                flow => return function.value(flow),
+            }
+        }
+    }
+}
+
+impl LoxFunction {
+    /// Runs the body once. A call the body returns from tail position comes
+    /// back as [`Flow::TailCall`], for the caller to make.
+    fn run(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Flow<'_>, Error> {
+        let mut environment = Environment::new(Rc::clone(&self.0.closure));
+        let (params, rest) = match &*self.0.declaration {
+            declaration if declaration.variadic => declaration.params.split_at(self.arity()),
+            declaration => (&declaration.params[..], &[][..]),
+        };
+        for (param, argument) in params.iter().zip(arguments) {
+            environment.define(param.symbol.clone(), argument.clone());
+        }
+        if let [rest] = rest {
+            let list = interpreter.heap.list(arguments[params.len()..].to_vec());
+            environment.define(rest.symbol.clone(), Value::List(LoxList(list)));
+        }
+        let enclosing = interpreter.program.replace(Rc::clone(&self.0.program));
+        let environment = interpreter.heap.environment(environment);
+        let flow =
+            interpreter.execute_block(&self.0.program, &self.0.declaration.body, environment);
+        interpreter.program = enclosing;
+        flow
+    }
+
+    /// The value of a call whose body finished with `flow`.
+    fn value(&self, flow: Flow<'_>) -> Result<Value, Error> {
+        match flow {
+            _ if self.0.is_initializer => {
+                self.0
+                    .closure
+                    .borrow()
+                    .get_at(0, 0, &Symbol::permanent("this"))
+            }
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Nil),
+        }
+    }
+}
+
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0.declaration, &other.0.declaration)
+            && Rc::ptr_eq(&self.0.closure, &other.0.closure)
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoxFunction({})", self.0.declaration.name.symbol)
+    }
+}
+
+/// A class declared in Lox. Calling it makes an instance.
+#[derive(Clone)]
+pub struct LoxClass(pub(crate) Rc<Class>);
+
+pub(crate) struct Class {
+    name: String,
+    pub(crate) superclass: Option<LoxClass>,
+    pub(crate) methods: HashMap<Symbol, LoxFunction>,
+    /// Fields of the class itself, which its instances do not share.
+    pub(crate) fields: RefCell<HashMap<Symbol, Value>>,
+    /// The fields declared in the class body, if any, which every new
+    /// instance starts with.
+    pub(crate) initializers: Option<Initializers>,
+}
+
+/// The fields declared in a class body, along with what their initializers
+/// need to run.
+pub(crate) struct Initializers {
+    fields: Vec<Field>,
+    /// The program the class came from, which holds the initializers.
+    program: Rc<Program>,
+    /// The scope the class was declared in, with `super` defined for a
+    /// subclass, as for its methods.
+    pub(crate) closure: Rc<RefCell<Environment>>,
+}
+
+impl LoxClass {
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The value of the class's own field `name`, if it has one. Fields of
+    /// a superclass are its own, not the subclass's.
+    pub fn get(&self, name: &Symbol) -> Option<Value> {
+        self.0.fields.borrow().get(name).cloned()
+    }
+
+    /// Sets the class's field `name`, adding it if the class has none yet.
+    pub fn set(&self, name: Symbol, value: Value) {
+        self.0.fields.borrow_mut().insert(name, value);
+    }
+
+    /// Sets the fields declared in this class and in its superclasses on
+    /// `instance`, those of superclasses first, so that a subclass can
+    /// override them.
+    fn initialize(
+        &self,
+        interpreter: &mut Interpreter,
+        instance: &LoxInstance,
+    ) -> Result<(), Error> {
+        if let Some(superclass) = &self.0.superclass {
+            superclass.initialize(interpreter, instance)?;
+        }
+        let Some(initializers) = &self.0.initializers else {
+            return Ok(());
+        };
+        let mut environment = Environment::new(Rc::clone(&initializers.closure));
+        environment.define(Symbol::permanent("this"), Value::Instance(instance.clone()));
+        let environment = interpreter.heap.environment(environment);
+        let enclosing = std::mem::replace(&mut interpreter.environment, environment);
+        let program = interpreter
+            .program
+            .replace(Rc::clone(&initializers.program));
+        let result = initializers.fields.iter().try_for_each(|field| {
+            let value = interpreter.evaluate(&initializers.program, field.initializer)?;
+            instance.set(field.name.symbol.clone(), value);
+            Ok(())
+        });
+        interpreter.program = program;
+        interpreter.environment = enclosing;
+        result
+    }
+
+    /// The method called `name`, defined by this class or inherited.
+    fn find_method(&self, name: &Symbol) -> Option<&LoxFunction> {
+        self.0.methods.get(name).or_else(|| {
+            self.0
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+impl Callable for LoxClass {
+    /// A class takes the arguments of its `init` method.
+    fn arity(&self) -> usize {
+        self.find_method(&Symbol::permanent("init"))
+            .map_or(0, Callable::arity)
+    }
+
+    fn variadic(&self) -> bool {
+        self.find_method(&Symbol::permanent("init"))
+            .is_some_and(Callable::variadic)
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error> {
+        let instance = LoxInstance(interpreter.heap.instance(Instance {
+            class: self.clone(),
+            fields: HashMap::new(),
+        }));
+        self.initialize(interpreter, &instance)?;
+        if let Some(initializer) = self.find_method(&Symbol::permanent("init")) {
+            initializer
+                .bind(instance.clone(), &mut interpreter.heap)
+                .call(interpreter, arguments)?;
+        }
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoxClass({})", self.0.name)
+    }
+}
+
+/// An instance of a [`LoxClass`]. Clones refer to the same instance.
+#[derive(Clone)]
+pub struct LoxInstance(pub(crate) Rc<RefCell<Instance>>);
+
+pub(crate) struct Instance {
+    pub(crate) class: LoxClass,
+    pub(crate) fields: HashMap<Symbol, Value>,
+}
+
+impl LoxInstance {
+    pub fn class(&self) -> LoxClass {
+        self.0.borrow().class.clone()
+    }
+
+    /// The value of the field `name`, if the instance has one.
+    pub fn get(&self, name: &Symbol) -> Option<Value> {
+        self.0.borrow().fields.get(name).cloned()
+    }
+
+    /// Sets the field `name`, adding it if the instance has none yet.
+    pub fn set(&self, name: Symbol, value: Value) {
+        self.0.borrow_mut().fields.insert(name, value);
+    }
+}
+
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoxInstance({})", self.class().0.name)
+    }
+}
+
+/// A fixed sequence of values, such as a function returns to return more
+/// than one. Tuples are equal if their elements are.
+#[derive(Clone, PartialEq)]
+pub struct LoxTuple(pub(crate) Rc<Box<[Value]>>);
+
+impl LoxTuple {
+    pub fn elements(&self) -> &[Value] {
+        &self.0
+    }
+}
+
+impl From<Vec<Value>> for LoxTuple {
+    fn from(elements: Vec<Value>) -> Self {
+        Self(Rc::new(elements.into_boxed_slice()))
+    }
+}
+
+impl fmt::Debug for LoxTuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LoxTuple").field(&self.elements()).finish()
+    }
+}
+
+/// A growable sequence of values, such as a rest parameter holds. Clones
+/// refer to the same list.
+#[derive(Clone)]
+pub struct LoxList(pub(crate) Rc<RefCell<Vec<Value>>>);
+
+impl LoxList {
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    /// The element at `index`, if the list is long enough to have one.
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.0.borrow().get(index).cloned()
+    }
+
+    pub fn push(&self, value: Value) {
+        self.0.borrow_mut().push(value);
+    }
+
+    /// The list's elements, copied out of it.
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.0.borrow().clone()
+    }
+
+    /// The property `name` that scripts read from the list: its `length`,
+    /// or one of its methods.
+    fn property(&self, name: &str) -> Option<Value> {
+        let list = self.clone();
+        let method = |arity, method: fn(&LoxList, &[Value]) -> Result<Value, Error>| {
+            let function = NativeFunction::new(name, arity, move |_, arguments: &[Value]| {
+                method(&list, arguments)
+            });
+            Some(Value::Native(function))
+        };
+        match name {
+            "length" => Some(Value::Number(self.len() as f64)),
+            "get" => method(1, |list, arguments| {
+                let index = list.index(&arguments[0])?;
+                Ok(list.0.borrow()[index].clone())
+            }),
+            "set" => method(2, |list, arguments| {
+                let index = list.index(&arguments[0])?;
+                list.0.borrow_mut()[index] = arguments[1].clone();
+                Ok(arguments[1].clone())
+            }),
+            "push" => method(1, |list, arguments| {
+                list.push(arguments[0].clone());
+                Ok(Value::Nil)
+            }),
+            "pop" => method(0, |list, _| {
+                Ok(list.0.borrow_mut().pop().unwrap_or(Value::Nil))
+            }),
+            _ => None,
+        }
+    }
+
+    /// `index` as the position of one of the list's elements.
+    fn index(&self, index: &Value) -> Result<usize, Error> {
+        let len = self.len();
+        match index {
+            Value::Number(number)
+                if number.fract() == 0.0 && *number >= 0.0 && *number < len as f64 =>
+            {
+                Ok(*number as usize)
+            }
+            index => Err(ErrorKind::ListIndex {
+                index: index.to_string(),
+                length: len,
+            }
+            .into()),
+        }
+    }
+}
+
+impl From<Vec<Value>> for LoxList {
+    fn from(elements: Vec<Value>) -> Self {
+        Self(Rc::new(RefCell::new(elements)))
+    }
+}
+
+impl PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.try_borrow() {
+            Ok(elements) => f.debug_tuple("LoxList").field(&*elements).finish(),
+            Err(_) => write!(f, "LoxList(..)"),
+        }
+    }
+}
+
+/// Something scripts can call: a Lox function, a class, which constructs
+/// an instance, or a native function.
+pub trait Callable {
+    /// How many arguments a call must pass, or if the callee is
+    /// [variadic](Callable::variadic), pass at least; the interpreter
+    /// checks this before calling.
+    fn arity(&self) -> usize;
+
+    /// Whether a call may pass more than [`arity`](Callable::arity)
+    /// arguments.
+    fn variadic(&self) -> bool {
+        false
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error>;
+}
+
+impl Value {
+    /// The value as something to call, if it is one.
+    pub fn as_callable(&self) -> Option<&dyn Callable> {
+        match self {
+            Self::Native(function) => Some(function),
+            Self::Function(function) => Some(function),
+            Self::Class(class) => Some(class),
+            _ => None,
+        }
+    }
+}
+
+/// Lets embedders decide what binary operators do, for instance to make
+/// `+` add two [`HostValue`]s holding vectors.
+pub trait Operators {
+    /// Evaluates `left operator right`, or returns `None` to let the
+    /// interpreter do so as usual. Called for every binary operation.
+    fn binary(
+        &self,
+        operator: BinaryOp,
+        left: &Value,
+        right: &Value,
+    ) -> Option<Result<Value, Error>>;
+}
+
+impl Value {
+    /// `nil` and `false` are falsey, everything else is truthy.
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Nil | Self::Boolean(false))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Boolean(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Host(_) => "host value",
+            Self::Native(_) | Self::Function(_) => "function",
+            Self::Class(_) => "class",
+            Self::Instance(_) => "instance",
+            Self::Tuple(_) => "tuple",
+            Self::List(_) => "list",
+        }
+    }
+
+    /// Describes how `self` differs from `other`, for reporting a failed
+    /// comparison in a test, or returns `None` if they are equal.
+    pub fn diff(&self, other: &Value) -> Option<String> {
+        if self == other {
+            return None;
+        }
+        Some(match (self, other) {
+            (Self::String(left), Self::String(right)) => {
+                let common = left
+                    .chars()
+                    .zip(right.chars())
+                    .take_while(|(left, right)| left == right)
+                    .count();
+                format!(
+                    "strings differ from character {common}:\n  left:  {left:?}\n  right: {right:?}"
+                )
+            }
+            (left, right) if left.type_name() != right.type_name() => format!(
+                "left is a {}, right is a {}:\n  left:  {}\n  right: {}",
+                left.type_name(),
+                right.type_name(),
+                left.quoted(),
+                right.quoted()
+            ),
+            (left, right) => format!(
+                "values differ:\n  left:  {}\n  right: {}",
+                left.quoted(),
+                right.quoted()
+            ),
+        })
+    }
+
+    /// The value as `print` shows it, but with strings in quotes, so that
+    /// `"1"` and `1` can be told apart.
+    fn quoted(&self) -> String {
+        match self {
+            Self::String(string) => format!("{:?}", &**string),
+            value => value.to_string(),
+        }
+    }
+}
+
+/// An error raised while running a program.
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// The line of the code that failed. Errors made outside the
+    /// interpreter, such as by an [`Operators`] hook, get the line of the
+    /// operation once they reach it.
+    pub line: Option<usize>,
+    /// The code that failed, for errors that can point at it more closely
+    /// than by line.
+    pub span: Option<Span>,
+}
+
+impl Error {
+    /// Places the error on `line`, unless it already has a line.
+    fn at(mut self, line: usize) -> Self {
+        self.line.get_or_insert(line);
+        self
+    }
+
+    /// Places the error at `span`, unless it already has a place.
+    fn within(mut self, span: Span) -> Self {
+        if self.line.is_none() {
+            self.line = Some(span.line);
+            self.span = Some(span);
+        }
+        self
+    }
+
+    /// The error as [`Display`](fmt::Display) shows it, but with the code
+    /// it spans quoted from `source`, the text the program was compiled
+    /// from.
+    pub fn report<'a>(&'a self, source: &'a [u8]) -> impl fmt::Display + 'a {
+        Report {
+            error: self,
+            source,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, source: Option<&[u8]>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(line) = self.line {
+            write!(f, " [line {line}]")?;
+        }
+        if let (Some(span), Some(source)) = (self.span, source) {
+            writeln!(f)?;
+            write_snippet(f, source, span)?;
+        }
+        if let ErrorKind::StackOverflow(trace) = &self.kind {
+            write!(f, "\n{trace}")?;
+        }
+        if let Some(help) = self.kind.help() {
+            write!(f, "\nhelp: {help}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            line: None,
+            span: None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+struct Report<'a> {
+    error: &'a Error,
+    source: &'a [u8],
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.write(f, Some(self.source))
+    }
+}
+
+/// Writes the line of `source` that `span` starts on, underlining the
+/// span as far as the end of that line.
+fn write_snippet(f: &mut fmt::Formatter<'_>, source: &[u8], span: Span) -> fmt::Result {
+    let start = span.start.min(source.len());
+    let line_start = source[..start]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let line_end = source[start..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(source.len(), |newline| start + newline);
+    let chars = |bytes: &[u8]| String::from_utf8_lossy(bytes).chars().count();
+    let indent = chars(&source[line_start..start]);
+    let width = chars(&source[start..span.end.clamp(start, line_end)]).max(1);
+    let text = String::from_utf8_lossy(&source[line_start..line_end]);
+    let gutter = " ".repeat(span.line.to_string().len());
+    writeln!(f, "{gutter} |")?;
+    writeln!(f, "{} | {}", span.line, text.trim_end())?;
+    write!(f, "{gutter} | {}{}", " ".repeat(indent), "^".repeat(width))
+}
+
+impl error::Error for Error {}
+
+/// `type_name` as a noun phrase: "a string", "an instance", or "nil".
+fn with_article(type_name: &str) -> String {
+    match type_name {
+        "nil" => type_name.to_owned(),
+        _ if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) => format!("an {type_name}"),
+        _ => format!("a {type_name}"),
+    }
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The operand of a unary operator had the wrong type, named by
+    /// `operand`.
+    Operand {
+        operator: UnaryOp,
+        operand: &'static str,
+    },
+    /// The operands of a binary operator had the wrong types, named by
+    /// `left` and `right`.
+    Operands {
+        operator: BinaryOp,
+        left: &'static str,
+        right: &'static str,
+    },
+    UndefinedVariable(String),
+    /// An instance has no field of this name, or a class no method.
+    UndefinedProperty(String),
+    /// A property was read from something other than an instance, a class
+    /// or a list.
+    PropertyOfNonInstance,
+    /// A field was set on something other than an instance or a class.
+    FieldOfNonInstance,
+    /// A value other than a function or class was called.
+    NotCallable,
+    /// A call passed `found` arguments to a function that takes
+    /// `expected`.
+    Arity {
+        expected: usize,
+        found: usize,
+    },
+    /// A call passed `found` arguments to a variadic function that takes
+    /// at least `expected`.
+    TooFewArguments {
+        expected: usize,
+        found: usize,
+    },
+    /// A list was indexed with something other than the position of one
+    /// of its `length` elements. This holds the index as `print` would
+    /// show it.
+    ListIndex {
+        index: String,
+        length: usize,
+    },
+    /// A call would have nested more deeply than the interpreter's
+    /// [`max_call_depth`](InterpreterBuilder::max_call_depth). The trace
+    /// shows where the calls in progress were.
+    StackOverflow(StackTrace),
+    /// A number was divided by zero while the interpreter treats that as
+    /// an error; see [`DivisionByZero`].
+    DivisionByZero,
+    /// A class was declared to inherit from something other than a class.
+    SuperclassNotClass,
+    /// A tuple was destructured into variables, but `found` is not a
+    /// tuple.
+    NotATuple(&'static str),
+    /// A tuple of `found` elements was destructured into `expected`
+    /// variables.
+    TupleLength {
+        expected: usize,
+        found: usize,
+    },
+    /// A built-in function was passed an argument it cannot take. This
+    /// holds the function's name and what it expected instead.
+    InvalidArgument {
+        function: &'static str,
+        expected: &'static str,
+    },
+    /// Writing what a script printed, or reading what it asked for, failed.
+    Io(io::Error),
+    /// An `assert` statement's condition was falsey. This holds its
+    /// message, as `print` would show it, if it has one.
+    AssertionFailed(Option<String>),
+    /// `expectEq()` was passed two different values. This holds how they
+    /// differ, as [`Value::diff`] describes it.
+    ExpectationFailed(String),
+    /// A script threw a value that nothing caught. This holds the value
+    /// as `print` would show it.
+    Exception(String),
+    /// The program still contains surface syntax, which
+    /// [`lower`](crate::unstable::lower::lower) must remove first.
+    NotLowered,
+}
+
+impl ErrorKind {
+    /// A suggestion for fixing the error, if there is one to make.
+    pub fn help(&self) -> Option<String> {
+        match self {
+            Self::Operands {
+                operator: BinaryOp::Add,
+                left,
+                right,
+            } => match (*left, *right) {
+                ("string", "instance") | ("instance", "string") => Some(
+                    "define toString() in the instance's class to add it to strings".to_owned(),
+                ),
+                ("string", other) | (other, "string") => Some(format!(
+                    "'+' does not convert between types; turn the {other} into a string first, \
+                     or add two numbers instead"
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Operand { operator, operand } => {
+                write!(
+                    f,
+                    "Operand of '{operator}' must be a number, but it is {}.",
+                    with_article(operand)
+                )
+            }
+            Self::Operands {
+                operator,
+                left,
+                right,
+            } => {
+                let expected = match operator {
+                    BinaryOp::Add => "two numbers or two strings",
+                    _ => "numbers",
+                };
+                write!(
+                    f,
+                    "Operands of '{operator}' must be {expected}, but the left operand is {} \
+                     and the right operand is {}.",
+                    with_article(left),
+                    with_article(right)
+                )
+            }
+            Self::UndefinedVariable(name) => write!(f, "Undefined variable '{name}'."),
+            Self::UndefinedProperty(name) => write!(f, "Undefined property '{name}'."),
+            Self::PropertyOfNonInstance => {
+                write!(f, "Only instances, classes and lists have properties.")
+            }
+            Self::FieldOfNonInstance => write!(f, "Only instances and classes have fields."),
+            Self::NotCallable => write!(f, "Can only call functions and classes."),
+            Self::Arity { expected, found } => {
+                write!(f, "Expected {expected} arguments but got {found}.")
+            }
+            Self::TooFewArguments { expected, found } => {
+                write!(f, "Expected at least {expected} arguments but got {found}.")
+            }
+            Self::ListIndex { index, length } => {
+                write!(
+                    f,
+                    "Index {index} is out of range for a list of length {length}."
+                )
+            }
+            Self::StackOverflow(_) => write!(f, "Stack overflow."),
+            Self::DivisionByZero => write!(f, "Division by zero."),
+            Self::SuperclassNotClass => write!(f, "Superclass must be a class."),
+            Self::NotATuple(found) => {
+                write!(
+                    f,
+                    "Can only destructure tuples, not {}.",
+                    with_article(found)
+                )
+            }
+            Self::TupleLength { expected, found } => write!(
+                f,
+                "Expected a tuple of {expected} elements but got {found}."
+            ),
+            Self::InvalidArgument { function, expected } => {
+                write!(f, "{function}() expects {expected}.")
+            }
+            Self::Io(error) => write!(f, "Input or output failed: {error}."),
+            Self::AssertionFailed(Some(message)) => write!(f, "Assertion failed: {message}"),
+            Self::AssertionFailed(None) => write!(f, "Assertion failed."),
+            Self::ExpectationFailed(diff) => write!(f, "Expectation failed: {diff}"),
+            Self::Exception(value) => write!(f, "Uncaught exception: {value}"),
+            Self::NotLowered => write!(f, "Program must be lowered before it is run."),
+        }
+    }
+}
+
+/// The calls in progress when a program failed, innermost first, each with
+/// the line it had reached. Deep stacks keep only their ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackTrace {
+    pub innermost: Vec<StackFrame>,
+    /// How many frames were left out between the innermost and outermost
+    /// ones.
+    pub omitted: usize,
+    /// Ends with the script itself.
+    pub outermost: Vec<StackFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    /// The function or class called, or `None` for the top level of the
+    /// script.
+    pub function: Option<String>,
+    pub line: usize,
+}
+
+/// How many frames a [`StackTrace`] keeps at each end.
+const TRACE_ENDS: usize = 5;
+
+impl fmt::Display for StackTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut frames = self.innermost.iter().map(Some).collect::<Vec<_>>();
+        if self.omitted > 0 {
+            frames.push(None);
+        }
+        frames.extend(self.outermost.iter().map(Some));
+        for (index, frame) in frames.into_iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            match frame {
+                Some(StackFrame {
+                    function: Some(function),
+                    line,
+                }) => write!(f, "[line {line}] in {function}()")?,
+                Some(StackFrame {
+                    function: None,
+                    line,
+                }) => write!(f, "[line {line}] in script")?,
+                None => write!(f, "... {} more calls ...", self.omitted)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a statement finished: by running to its end, or by jumping out of
+/// the loops around it.
+enum Flow<'a> {
+    Normal,
+    /// A `break`, with the label of the loop it leaves, if it names one.
+    Break(Option<&'a str>),
+    /// A `continue`, with the label of the loop it continues, if it names
+    /// one.
+    Continue(Option<&'a str>),
+    /// A `return` from the function being called, with its value.
+    Return(Value),
+    /// A `return` of a call to a Lox function, with the arguments to call
+    /// it with. The function returning makes the call once its own body
+    /// has finished, so that the two do not nest.
+    TailCall(LoxFunction, Vec<Value>),
+}
+
+pub struct Interpreter {
+    /// The scope the code being run declares its variables in.
+    environment: Rc<RefCell<Environment>>,
+    globals: Rc<RefCell<Environment>>,
+    operators: Option<Box<dyn Operators>>,
+    /// The program being run, once a function declared in it has needed
+    /// to share it.
+    program: Option<Rc<Program>>,
+    /// The calls in progress, outermost first.
+    calls: Vec<Call>,
+    max_call_depth: usize,
+    division_by_zero: DivisionByZero,
+    heap: Heap,
+    /// Where `print` writes.
+    output: Box<dyn Write>,
+    /// Where `readLine()` reads from, or standard input if unset.
+    input: Option<Box<dyn BufRead>>,
+    /// The value being thrown, while an [`ErrorKind::Exception`] unwinds
+    /// to the `catch` clause that takes it. Errors must be [`Send`], and
+    /// values are not, so the value waits here instead.
+    exception: Option<Value>,
+    /// The built-in `Error` class, whose instances stand for runtime
+    /// errors that `catch` clauses take.
+    error_class: LoxClass,
+}
+
+/// An output for an interpreter that keeps what is written in memory.
+/// Clones share the buffer, so keep one to read back what scripts print.
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What has been written so far, with invalid UTF-8 replaced.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    /// Takes what has been written so far, leaving the buffer empty.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.borrow_mut())
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What dividing a number by zero, with `/` or `%`, does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DivisionByZero {
+    /// Gives infinity or NaN, as IEEE 754 arithmetic does and jlox
+    /// inherits from Java.
+    #[default]
+    Ieee,
+    /// Fails with [`ErrorKind::DivisionByZero`].
+    Error,
+}
+
+/// A call in progress: what was called, and from which line.
+struct Call {
+    callee: Value,
+    line: usize,
+}
+
+/// How deeply calls may nest unless an [`InterpreterBuilder`] says
+/// otherwise. Calls in tail position do not count, since they replace
+/// their caller.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+impl Default for Interpreter {
+    /// An interpreter whose globals are the built-in functions.
+    fn default() -> Self {
+        let mut heap = Heap::default();
+        let globals = heap.environment(Environment::default());
+        globals.borrow_mut().define(
+            Symbol::permanent("clock"),
+            Value::Native(NativeFunction::new("clock", 0, clock)),
+        );
+        globals.borrow_mut().define(
+            Symbol::permanent("readLine"),
+            Value::Native(NativeFunction::new("readLine", 0, read_line)),
+        );
+        globals.borrow_mut().define(
+            Symbol::permanent("ord"),
+            Value::Native(NativeFunction::new("ord", 1, ord)),
+        );
+        globals.borrow_mut().define(
+            Symbol::permanent("chr"),
+            Value::Native(NativeFunction::new("chr", 1, chr)),
+        );
+        globals.borrow_mut().define(
+            Symbol::permanent("expectEq"),
+            Value::Native(NativeFunction::new("expectEq", 2, expect_eq)),
+        );
+        let error_class = LoxClass(heap.class(Class {
+            name: "Error".to_owned(),
+            superclass: None,
+            methods: HashMap::new(),
+            fields: RefCell::default(),
+            initializers: None,
+        }));
+        globals.borrow_mut().define(
+            Symbol::permanent("Error"),
+            Value::Class(error_class.clone()),
+        );
+        Self {
+            environment: Rc::clone(&globals),
+            globals,
+            operators: None,
+            program: None,
+            calls: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            division_by_zero: DivisionByZero::default(),
+            heap,
+            output: Box::new(io::stdout()),
+            input: None,
+            exception: None,
+            error_class,
+        }
+    }
+}
+
+impl Drop for Interpreter {
+    /// Lets go of the scopes the interpreter holds, then collects what
+    /// cycles alone keep alive, such as the global functions, which close
+    /// over the globals that hold them.
+    fn drop(&mut self) {
+        self.environment = Rc::default();
+        self.globals = Rc::default();
+        self.heap.collect();
+    }
+}
+
+impl fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("environment", &self.environment)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An interpreter that writes what scripts print to `output` rather
+    /// than to standard output. Pass an [`OutputBuffer`] to capture it.
+    pub fn with_output(output: impl Write + 'static) -> Self {
+        let mut interpreter = Self::default();
+        interpreter.output = Box::new(output);
+        interpreter
+    }
+
+    /// Starts configuring an interpreter in explicit phases: register
+    /// globals, then load a prelude, then [`InterpreterBuilder::build`].
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::default()
+    }
+
+    /// Frees the scopes, functions, classes and instances that nothing
+    /// uses but cycles among themselves. The interpreter also does this on
+    /// its own as it allocates them.
+    pub fn collect_garbage(&mut self) {
+        self.heap.collect();
+    }
+
+    /// The cell holding the global variable `name`, if one is declared.
+    /// Scripts share it: setting it changes what they read next, and it
+    /// holds whatever they last assigned.
+    pub fn global_cell(&self, name: &str) -> Option<Cell> {
+        self.globals.borrow().cell(&Symbol::new(name))
+    }
+
+    /// Runs `program`, returning the value of its
+    /// [`result`](Program::result) expression if it has one.
+    pub fn interpret(&mut self, program: &Program) -> Result<Option<Value>, Error> {
+        let enclosing = self.program.take();
+        let result = self.run(program);
+        self.program = enclosing;
+        result
+    }
+
+    fn run(&mut self, program: &Program) -> Result<Option<Value>, Error> {
+        for statement in &program.statements {
+            self.execute(program, statement)?;
+        }
+        program
+            .result
+            .map(|result| self.evaluate(program, result))
+            .transpose()
+    }
+
+    /// `program`, the program being run, shared so that functions declared
+    /// in it can keep it. It is copied the first time it is needed.
+    fn shared_program(&mut self, program: &Program) -> Rc<Program> {
+        Rc::clone(self.program.get_or_insert_with(|| Rc::new(program.clone())))
+    }
+
+    /// `declaration` as a function closing over the current scope.
+    fn function(
+        &mut self,
+        program: &Program,
+        declaration: &Rc<Function>,
+        is_initializer: bool,
+    ) -> LoxFunction {
+        let closure = Closure {
+            declaration: Rc::clone(declaration),
+            program: self.shared_program(program),
+            closure: Rc::clone(&self.environment),
+            is_initializer,
+        };
+        LoxFunction(self.heap.closure(closure))
+    }
+
+    /// Runs `statement`. Valid programs only jump within loops, so at the
+    /// top level the flow is always normal.
+    fn execute<'a>(
+        &mut self,
+        program: &Program,
+        statement: &'a Statement,
+    ) -> Result<Flow<'a>, Error> {
+        // The evaluator recurses as deeply as the Lox code it runs.
+        stack::grow(|| self.execute_statement(program, statement))
+    }
+
+    fn execute_statement<'a>(
+        &mut self,
+        program: &Program,
+        statement: &'a Statement,
+    ) -> Result<Flow<'a>, Error> {
+        match &statement.kind {
+            StatementKind::Assert {
+                condition, message, ..
+            } => {
+                if self.evaluate(program, *condition)?.is_truthy() {
+                    return Ok(Flow::Normal);
+                }
+                let message = match message {
+                    Some(message) => {
+                        let message = self.evaluate(program, *message)?;
+                        let line = program[*condition].span.line;
+                        Some(self.stringify(message, line)?.to_string())
+                    }
+                    None => None,
+                };
+                Err(Error::from(ErrorKind::AssertionFailed(message))
+                    .within(program[*condition].span))
+            }
+            StatementKind::Block(statements) => {
+                let environment = Environment::new(Rc::clone(&self.environment));
+                let environment = self.heap.environment(environment);
+                self.execute_block(program, statements, environment)
+            }
+            StatementKind::Break { label, .. } => Ok(Flow::Break(
+                label.as_ref().map(|label| label.lexeme.as_str()),
+            )),
+            StatementKind::Continue { label, .. } => Ok(Flow::Continue(
+                label.as_ref().map(|label| label.lexeme.as_str()),
+            )),
+            StatementKind::Expression(expr) => {
+                self.evaluate(program, *expr)?;
+                Ok(Flow::Normal)
+            }
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate(program, *condition)?.is_truthy() {
+                    self.execute(program, then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(program, else_branch)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            StatementKind::Print(expr) => {
+                let value = self.evaluate(program, *expr)?;
+                let value = self.stringify(value, statement.span.line)?;
+                writeln!(self.output, "{value}")
+                    .map_err(|error| Error::from(ErrorKind::Io(error)).at(statement.span.line))?;
+                Ok(Flow::Normal)
+            }
+            StatementKind::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(program, *initializer)?,
+                    None => Value::Nil,
+                };
+                self.environment
+                    .borrow_mut()
+                    .define(name.symbol.clone(), value);
+                Ok(Flow::Normal)
+            }
+            StatementKind::VarTuple { names, initializer } => {
+                let value = self.evaluate(program, *initializer)?;
+                let tuple = unpack(&value, names.len())
+                    .map_err(|error| error.within(program[*initializer].span))?;
+                let mut environment = self.environment.borrow_mut();
+                for (name, element) in names.iter().zip(tuple.elements()) {
+                    environment.define(name.symbol.clone(), element.clone());
+                }
+                Ok(Flow::Normal)
+            }
+            StatementKind::While {
+                condition,
+                body,
+                increment,
+                label,
+            } => {
+                let label = label.as_ref().map(|label| label.lexeme.as_str());
+                while self.evaluate(program, *condition)?.is_truthy() {
+                    match self.execute(program, body)? {
+                        Flow::Normal => {}
+                        Flow::Break(target) if target.is_none() || target == label => break,
+                        Flow::Continue(target) if target.is_none() || target == label => {}
+                        // The jump is to a loop further out.
+                        flow => return Ok(flow),
+                    }
+                    if let Some(increment) = increment {
+                        self.evaluate(program, *increment)?;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            StatementKind::For { .. } => Err(ErrorKind::NotLowered.into()),
+            StatementKind::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+                ..
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => match self.evaluate(program, *superclass)? {
+                        Value::Class(superclass) => Some(superclass),
+                        _ => {
+                            return Err(Error::from(ErrorKind::SuperclassNotClass)
+                                .at(program[*superclass].span.line))
+                        }
+                    },
+                    None => None,
+                };
+                let enclosing = superclass.as_ref().map(|superclass| {
+                    let mut environment = Environment::new(Rc::clone(&self.environment));
+                    environment
+                        .define(Symbol::permanent("super"), Value::Class(superclass.clone()));
+                    let environment = self.heap.environment(environment);
+                    std::mem::replace(&mut self.environment, environment)
+                });
+                let methods = methods
+                    .iter()
+                    .map(|method| {
+                        let is_initializer = &*method.name.symbol == "init";
+                        let function = self.function(program, method, is_initializer);
+                        (method.name.symbol.clone(), function)
+                    })
+                    .collect();
+                let initializers = (!fields.is_empty()).then(|| Initializers {
+                    fields: fields.clone(),
+                    program: self.shared_program(program),
+                    closure: Rc::clone(&self.environment),
+                });
+                if let Some(enclosing) = enclosing {
+                    self.environment = enclosing;
+                }
+                let class = LoxClass(self.heap.class(Class {
+                    name: name.token.lexeme.clone(),
+                    superclass,
+                    methods,
+                    fields: RefCell::default(),
+                    initializers,
+                }));
+                self.environment
+                    .borrow_mut()
+                    .define(name.symbol.clone(), Value::Class(class));
+                Ok(Flow::Normal)
+            }
+            StatementKind::Function(declaration) => {
+                let function = self.function(program, declaration, false);
+                self.environment
+                    .borrow_mut()
+                    .define(declaration.name.symbol.clone(), Value::Function(function));
+                Ok(Flow::Normal)
+            }
+            StatementKind::Throw { value, .. } => {
+                let line = statement.span.line;
+                let value = self.evaluate(program, *value)?;
+                let shown = self.stringify(value.clone(), line)?.to_string();
+                self.exception = Some(value);
+                Err(Error::from(ErrorKind::Exception(shown)).at(line))
+            }
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let line = statement.span.line;
+                let result = self.execute(program, body);
+                let result = match (self.settle(result, line), catch) {
+                    (Err(error), Some(catch)) if !matches!(error.kind, ErrorKind::NotLowered) => {
+                        let exception = self.caught(error);
+                        let mut environment = Environment::new(Rc::clone(&self.environment));
+                        environment.define(catch.name.symbol.clone(), exception);
+                        let environment = self.heap.environment(environment);
+                        let result =
+                            self.execute_block(program, slice::from_ref(&*catch.body), environment);
+                        self.settle(result, line)
+                    }
+                    (result, _) => result,
+                };
+                let Some(finally) = finally else {
+                    return result;
+                };
+                // The clause may throw and catch exceptions of its own.
+                let exception = self.exception.take();
+                match self.execute(program, finally)? {
+                    Flow::Normal => {
+                        self.exception = exception;
+                        result
+                    }
+                    // Leaving the clause early forgets the outcome of the
+                    // rest of the statement, even an error.
+                    flow => Ok(flow),
+                }
+            }
+            StatementKind::Return { value: None, .. } => Ok(Flow::Return(Value::Nil)),
+            StatementKind::Return {
+                value: Some(value), ..
+            } => {
+                let ExprKind::Call {
+                    callee, arguments, ..
+                } = &program[*value].kind
+                else {
+                    return Ok(Flow::Return(self.evaluate(program, *value)?));
+                };
+                let callee = self.evaluate(program, *callee)?;
+                let arguments = self.evaluate_all(program, arguments)?;
+                match callee {
+                    Value::Function(function)
+                        if check_arity(&function, arguments.len()).is_ok() =>
+                    {
+                        Ok(Flow::TailCall(function, arguments))
+                    }
+                    callee => {
+                        let line = program[*value].span.line;
+                        Ok(Flow::Return(self.call(&callee, &arguments, line)?))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes the call a `return` in `result` left for its caller to make,
+    /// if there is one. Calls in a `try` statement must be made inside it,
+    /// for it to see what they throw.
+    fn settle<'a>(
+        &mut self,
+        result: Result<Flow<'a>, Error>,
+        line: usize,
+    ) -> Result<Flow<'a>, Error> {
+        match result {
+            Ok(Flow::TailCall(function, arguments)) => self
+                .call(&Value::Function(function), &arguments, line)
+                .map(Flow::Return),
+            result => result,
+        }
+    }
+
+    /// The value a `catch` clause takes for `error`: the value thrown, or
+    /// for an error the interpreter raised itself, an instance of the
+    /// built-in `Error` class with the error's `message` and `line`.
+    fn caught(&mut self, error: Error) -> Value {
+        let kind = match error.kind {
+            ErrorKind::Exception(shown) => {
+                // Only native functions can fail with an exception that
+                // was never thrown.
+                return self
+                    .exception
+                    .take()
+                    .unwrap_or_else(|| Value::String(Symbol::new(&shown)));
+            }
+            kind => kind,
+        };
+        let mut fields = HashMap::from([(
+            Symbol::permanent("message"),
+            Value::String(Symbol::new(&kind.to_string())),
+        )]);
+        if let Some(line) = error.line {
+            fields.insert(Symbol::permanent("line"), Value::Number(line as f64));
+        }
+        Value::Instance(LoxInstance(self.heap.instance(Instance {
+            class: self.error_class.clone(),
+            fields,
+        })))
+    }
+
+    /// Executes `statements` in `environment`, restoring the current
+    /// environment afterwards even if execution fails.
+    fn execute_block<'a>(
+        &mut self,
+        program: &Program,
+        statements: &'a [Statement],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Flow<'a>, Error> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let mut result = Ok(Flow::Normal);
+        for statement in statements {
+            result = self.execute(program, statement);
+            if !matches!(result, Ok(Flow::Normal)) {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
+
+    fn evaluate(&mut self, program: &Program, expr: ExprId) -> Result<Value, Error> {
+        stack::grow(|| self.evaluate_expr(program, expr))
+    }
+
+    fn evaluate_expr(&mut self, program: &Program, expr: ExprId) -> Result<Value, Error> {
+        let line = program[expr].span.line;
+        match &program[expr].kind {
+            ExprKind::Assign { name, value } => {
+                let value = self.evaluate(program, *value)?;
+                self.assign(program, expr, &name.symbol, value.clone())
+                    .map_err(|error| error.at(line))?;
+                Ok(value)
+            }
+            ExprKind::AssignTuple { targets, value } => {
+                let value = self.evaluate(program, *value)?;
+                let tuple = unpack(&value, targets.len())
+                    .map_err(|error| error.within(program[expr].span))?;
+                for (target, element) in targets.iter().zip(tuple.elements()) {
+                    let ExprKind::Variable(name) = &program[*target].kind else {
+                        unreachable!("the parser only makes variables targets");
+                    };
+                    self.assign(program, *target, &name.symbol, element.clone())
+                        .map_err(|error| error.at(line))?;
+                }
+                Ok(value)
+            }
+            ExprKind::Tuple(elements) => {
+                let elements = self.evaluate_all(program, elements)?;
+                Ok(Value::Tuple(LoxTuple(self.heap.tuple(elements))))
+            }
+            ExprKind::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.evaluate(program, *left)?;
+                let right = self.evaluate(program, *right)?;
+                if let Some(result) = self
+                    .operators
+                    .as_ref()
+                    .and_then(|operators| operators.binary(*operator, &left, &right))
+                {
+                    return result.map_err(|error| error.within(program[expr].span));
+                }
+                if let Value::Instance(instance) = &left {
+                    if let Some(result) = self.overloaded(instance, *operator, &right, line) {
+                        return result;
+                    }
+                }
+                // An instance added to a string joins it as its `toString()`.
+                let (left, right) = match (operator, &left, &right) {
+                    (BinaryOp::Add, Value::String(_), Value::Instance(_)) => {
+                        (left, self.stringify(right, line)?)
+                    }
+                    (BinaryOp::Add, Value::Instance(_), Value::String(_)) => {
+                        (self.stringify(left, line)?, right)
+                    }
+                    _ => (left, right),
+                };
+                binary(*operator, &left, &right, self.division_by_zero)
+                    .map_err(|error| error.within(program[expr].span))
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                let callee = self.evaluate(program, *callee)?;
+                let arguments = self.evaluate_all(program, arguments)?;
+                self.call(&callee, &arguments, line)
+            }
+            ExprKind::Grouping(expr) => self.evaluate(program, *expr),
+            ExprKind::Literal(literal) => Ok(match (&literal.token.token_type, &literal.string) {
+                (TokenType::Nil, _) => Value::Nil,
+                (TokenType::True, _) => Value::Boolean(true),
+                (TokenType::False, _) => Value::Boolean(false),
+                (TokenType::Number(number), _) => Value::Number(*number),
+                (_, Some(string)) => Value::String(string.clone()),
+                _ => unreachable!("the parser only creates literals from literal tokens"),
+            }),
+            ExprKind::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.evaluate(program, *left)?;
+                match (operator, left.is_truthy()) {
+                    (LogicalOp::Or, true) | (LogicalOp::And, false) => Ok(left),
+                    (LogicalOp::Or, false) | (LogicalOp::And, true) => {
+                        self.evaluate(program, *right)
+                    }
+                }
+            }
+            ExprKind::Unary { operator, right } => {
+                let right = self.evaluate(program, *right)?;
+                match (operator, right) {
+                    (UnaryOp::Not, right) => Ok(Value::Boolean(!right.is_truthy())),
+                    (UnaryOp::Negate, Value::Number(number)) => Ok(Value::Number(-number)),
+                    (operator, operand) => Err(Error::from(ErrorKind::Operand {
+                        operator: *operator,
+                        operand: operand.type_name(),
+                    })
+                    .within(program[expr].span)),
+                }
+            }
+            ExprKind::Variable(name) => self
+                .look_up(program, expr, &name.symbol)
+                .map_err(|error| error.at(line)),
+            ExprKind::This(keyword) => self
+                .look_up(program, expr, &keyword.symbol)
+                .map_err(|error| error.at(line)),
+            ExprKind::Super { keyword, method } => {
+                let superclass = self.look_up(program, expr, &keyword.symbol)?;
+                // `this` is bound in the scope just inside the one that
+                // binds `super`, and alone in it.
+                let name = &Symbol::permanent("this");
+                let this = match program.binding(expr) {
+                    Some(Binding::Local { depth, .. }) => {
+                        self.environment.borrow().get_at(depth - 1, 0, name)
+                    }
+                    _ => self.environment.borrow().get(name),
+                }?;
+                match (superclass, this) {
+                    (Value::Class(superclass), Value::Instance(this)) => {
+                        match superclass.find_method(&method.symbol) {
+                            Some(method) => Ok(Value::Function(method.bind(this, &mut self.heap))),
+                            None => Err(Error::from(ErrorKind::UndefinedProperty(
+                                method.symbol.to_string(),
+                            ))
+                            .at(line)),
+                        }
+                    }
+                    _ => unreachable!("`super` and `this` are bound by the interpreter"),
+                }
+            }
+            ExprKind::Get { object, name } => match self.evaluate(program, *object)? {
+                Value::Instance(instance) => {
+                    // Fields shadow methods of the same name.
+                    if let Some(value) = instance.get(&name.symbol) {
+                        return Ok(value);
+                    }
+                    match instance.class().find_method(&name.symbol) {
+                        Some(method) => Ok(Value::Function(method.bind(instance, &mut self.heap))),
+                        None => Err(ErrorKind::UndefinedProperty(name.symbol.to_string()).into()),
+                    }
+                }
+                Value::Class(class) => class
+                    .get(&name.symbol)
+                    .ok_or_else(|| ErrorKind::UndefinedProperty(name.symbol.to_string()).into()),
+                Value::List(list) => list
+                    .property(&name.symbol)
+                    .ok_or_else(|| ErrorKind::UndefinedProperty(name.symbol.to_string()).into()),
+                _ => Err(ErrorKind::PropertyOfNonInstance.into()),
+            }
+            .map_err(|error: Error| error.at(name.token.span.line)),
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.evaluate(program, *object)?;
+                if !matches!(object, Value::Instance(_) | Value::Class(_)) {
+                    return Err(Error::from(ErrorKind::FieldOfNonInstance).at(name.token.span.line));
+                }
+                let value = self.evaluate(program, *value)?;
+                let name = name.symbol.clone();
+                match object {
+                    Value::Instance(instance) => instance.set(name, value.clone()),
+                    Value::Class(class) => class.set(name, value.clone()),
+                    _ => unreachable!("the object was checked above"),
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Applies `operator` to `left` and `right` by calling the method of
+    /// `left` that overloads it, if its class defines one. `!=` calls
+    /// `eq` and negates the result.
+    fn overloaded(
+        &mut self,
+        left: &LoxInstance,
+        operator: BinaryOp,
+        right: &Value,
+        line: usize,
+    ) -> Option<Result<Value, Error>> {
+        let name = match operator {
+            BinaryOp::Equal | BinaryOp::NotEqual => "eq",
+            BinaryOp::Greater => "gt",
+            BinaryOp::GreaterEqual => "ge",
+            BinaryOp::Less => "lt",
+            BinaryOp::LessEqual => "le",
+            BinaryOp::Add => "plus",
+            BinaryOp::Subtract => "minus",
+            BinaryOp::Multiply => "times",
+            BinaryOp::Divide => "divide",
+            BinaryOp::Remainder => "mod",
+        };
+        let method = left
+            .class()
+            .find_method(&Symbol::permanent(name))?
+            .bind(left.clone(), &mut self.heap);
+        let result = self.call(&Value::Function(method), slice::from_ref(right), line);
+        Some(match operator {
+            BinaryOp::NotEqual => result.map(|value| Value::Boolean(!value.is_truthy())),
+            _ => result,
+        })
+    }
+
+    /// What `value` shows as: the result of its `toString()` method, if it
+    /// is an instance whose class defines one, or else `value` itself.
+    fn stringify(&mut self, value: Value, line: usize) -> Result<Value, Error> {
+        let Value::Instance(instance) = &value else {
+            return Ok(value);
+        };
+        match instance.class().find_method(&Symbol::permanent("toString")) {
+            Some(method) => {
+                let method = method.bind(instance.clone(), &mut self.heap);
+                self.call(&Value::Function(method), &[], line)
+            }
+            None => Ok(value),
+        }
+    }
+
+    fn evaluate_all(&mut self, program: &Program, exprs: &[ExprId]) -> Result<Vec<Value>, Error> {
+        exprs
+            .iter()
+            .map(|expr| self.evaluate(program, *expr))
+            .collect()
+    }
+
+    /// Calls `callee` from `line`, checking first that it takes
+    /// `arguments`.
+    fn call(&mut self, callee: &Value, arguments: &[Value], line: usize) -> Result<Value, Error> {
+        let result = match callee.as_callable() {
+            Some(callable) => match check_arity(callable, arguments.len()) {
+                Err(error) => Err(error.into()),
+                Ok(()) if self.calls.len() >= self.max_call_depth => {
+                    Err(ErrorKind::StackOverflow(self.stack_trace(line)).into())
+                }
+                Ok(()) => {
+                    self.calls.push(Call {
+                        callee: callee.clone(),
+                        line,
+                    });
+                    let result = callable.call(self, arguments);
+                    self.calls.pop();
+                    result
+                }
+            },
+            None => Err(ErrorKind::NotCallable.into()),
+        };
+        result.map_err(|error| error.at(line))
+    }
+
+    /// The calls in progress, as of a call from `line`.
+    fn stack_trace(&self, line: usize) -> StackTrace {
+        // Each call has reached the line of the call it made. The script
+        // has reached the line of the outermost call.
+        let lines = self.calls.iter().map(|call| call.line).chain([line]);
+        let functions = std::iter::once(None).chain(self.calls.iter().map(|call| {
+            Some(match &call.callee {
+                Value::Function(function) => function.0.declaration.name.token.lexeme.clone(),
+                Value::Class(class) => class.name().to_owned(),
+                Value::Native(native) => native.name().to_owned(),
+                callee => callee.to_string(),
+            })
+        }));
+        let mut frames = functions
+            .zip(lines)
+            .map(|(function, line)| StackFrame { function, line })
+            .collect::<Vec<_>>();
+        frames.reverse();
+        let omitted = frames.len().saturating_sub(2 * TRACE_ENDS);
+        let outermost = frames.split_off(frames.len() - TRACE_ENDS.min(frames.len()));
+        frames.truncate(TRACE_ENDS);
+        StackTrace {
+            innermost: frames,
+            omitted,
+            outermost,
+        }
+    }
+
+    /// Finds the value of the variable `name`, referred to by `expr`.
+    /// Assigns `value` to the variable `name`, which `expr` refers to.
+    fn assign(
+        &mut self,
+        program: &Program,
+        expr: ExprId,
+        name: &Symbol,
+        value: Value,
+    ) -> Result<(), Error> {
+        match program.binding(expr) {
+            Some(Binding::Local { depth, slot }) => self
+                .environment
+                .borrow_mut()
+                .assign_at(depth, slot, name, value),
+            Some(Binding::Global) => self.globals.borrow_mut().assign(name, value),
+            None => self.environment.borrow_mut().assign(name, value),
+        }
+    }
+
+    fn look_up(&self, program: &Program, expr: ExprId, name: &Symbol) -> Result<Value, Error> {
+        match program.binding(expr) {
+            Some(Binding::Local { depth, slot }) => {
+                self.environment.borrow().get_at(depth, slot, name)
+            }
+            Some(Binding::Global) => self.globals.borrow().get(name),
+            // Without a resolution, search every scope.
+            None => self.environment.borrow().get(name),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InterpreterBuilder {
+    globals: Vec<(String, Value)>,
+    prelude: Vec<Program>,
+    operators: Option<Box<dyn Operators>>,
+    max_call_depth: Option<usize>,
+    gc_stress: bool,
+    division_by_zero: DivisionByZero,
+    output: Option<Box<dyn Write>>,
+    input: Option<Box<dyn BufRead>>,
+}
+
+impl fmt::Debug for InterpreterBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterpreterBuilder")
+            .field("globals", &self.globals)
+            .field("prelude", &self.prelude)
+            .finish_non_exhaustive()
+    }
+}
+
+impl InterpreterBuilder {
+    /// Defines a global variable visible to the prelude and to scripts.
+    pub fn global(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.globals.push((name.into(), value));
+        self
+    }
+
+    /// Defines a global [`NativeFunction`] named `name`, for scripts to
+    /// call with `arity` arguments.
+    pub fn native(
+        self,
+        name: impl Into<String>,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, Error> + 'static,
+    ) -> Self {
+        let name = name.into();
+        let function = NativeFunction::new(name.clone(), arity, function);
+        self.global(name, Value::Native(function))
+    }
+
+    /// Adds a compiled program to run once, when the interpreter is built.
+    /// Declarations it makes are visible to every script run afterwards.
+    pub fn prelude(mut self, program: Program) -> Self {
+        self.prelude.push(program);
+        self
+    }
+
+    /// Installs a hook that sees every binary operation before the
+    /// interpreter does, including those in the prelude.
+    pub fn operators(mut self, operators: impl Operators + 'static) -> Self {
+        self.operators = Some(Box::new(operators));
+        self
+    }
+
+    /// Limits how deeply calls may nest, in place of
+    /// [`DEFAULT_MAX_CALL_DEPTH`]. A call past the limit fails with
+    /// [`ErrorKind::StackOverflow`].
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    /// Collects garbage before every allocation rather than now and then.
+    /// This is slow, but makes a bug in the collector show up as soon as
+    /// it matters.
+    pub fn gc_stress(mut self, stress: bool) -> Self {
+        self.gc_stress = stress;
+        self
+    }
+
+    /// Chooses what dividing by zero does, [`DivisionByZero::Ieee`] unless
+    /// set.
+    pub fn division_by_zero(mut self, behavior: DivisionByZero) -> Self {
+        self.division_by_zero = behavior;
+        self
+    }
+
+    /// Sends what scripts print to `output` rather than to standard
+    /// output, starting with the prelude.
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Has `readLine()` read from `input` rather than from standard input.
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    pub fn build(self) -> Result<Interpreter, Error> {
+        let mut interpreter = Interpreter::new();
+        if let Some(output) = self.output {
+            interpreter.output = output;
+        }
+        interpreter.input = self.input;
+        interpreter.operators = self.operators;
+        interpreter.heap.stress = self.gc_stress;
+        interpreter.division_by_zero = self.division_by_zero;
+        if let Some(depth) = self.max_call_depth {
+            interpreter.max_call_depth = depth;
+        }
+        for (name, value) in self.globals {
+            interpreter
+                .globals
+                .borrow_mut()
+                .define(Symbol::permanent(&name), value);
+        }
+        for program in &self.prelude {
+            interpreter.interpret(program)?;
+        }
+        Ok(interpreter)
+    }
+}
+
+/// The `clock()` built-in: seconds since the Unix epoch, to time scripts
+/// with.
+fn clock(_: &mut Interpreter, _: &[Value]) -> Result<Value, Error> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(Value::Number(since_epoch.as_secs_f64()))
+}
+
+/// The code point of a string of one character, such as a char literal.
+fn ord(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error> {
+    let mut chars = match &arguments[0] {
+        Value::String(string) => string.chars(),
+        _ => "".chars(),
+    };
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Value::Number(f64::from(u32::from(c)))),
+        _ => Err(ErrorKind::InvalidArgument {
+            function: "ord",
+            expected: "a string of one character",
+        }
+        .into()),
+    }
+}
+
+/// The string of one character whose code point is the argument.
+fn chr(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error> {
+    let c = match arguments[0] {
+        Value::Number(code)
+            if code.fract() == 0.0 && (0.0..=f64::from(u32::MAX)).contains(&code) =>
+        {
+            char::from_u32(code as u32)
+        }
+        _ => None,
+    };
+    match c {
+        Some(c) => Ok(Value::String(Symbol::new(c.encode_utf8(&mut [0; 4])))),
+        None => Err(ErrorKind::InvalidArgument {
+            function: "chr",
+            expected: "the code point of a Unicode character",
+        }
+        .into()),
+    }
+}
+
+/// Fails, saying how they differ, unless the two arguments are equal.
+fn expect_eq(_: &mut Interpreter, arguments: &[Value]) -> Result<Value, Error> {
+    match arguments[0].diff(&arguments[1]) {
+        None => Ok(Value::Nil),
+        Some(diff) => Err(ErrorKind::ExpectationFailed(diff).into()),
+    }
+}
+
+/// Checks that `callable` takes `found` arguments.
+fn check_arity(callable: &dyn Callable, found: usize) -> Result<(), ErrorKind> {
+    let expected = callable.arity();
+    match callable.variadic() {
+        false if found != expected => Err(ErrorKind::Arity { expected, found }),
+        true if found < expected => Err(ErrorKind::TooFewArguments { expected, found }),
+        _ => Ok(()),
+    }
+}
+
+/// `value` as a tuple of `count` elements, for destructuring.
+fn unpack(value: &Value, count: usize) -> Result<&LoxTuple, Error> {
+    match value {
+        Value::Tuple(tuple) if tuple.elements().len() == count => Ok(tuple),
+        Value::Tuple(tuple) => Err(ErrorKind::TupleLength {
+            expected: count,
+            found: tuple.elements().len(),
+        }
+        .into()),
+        value => Err(ErrorKind::NotATuple(value.type_name()).into()),
+    }
+}
+
+/// The `readLine()` built-in: the next line of input without its line
+/// ending, or `nil` at the end of the input.
+fn read_line(interpreter: &mut Interpreter, _: &[Value]) -> Result<Value, Error> {
+    let mut line = String::new();
+    let read = match &mut interpreter.input {
+        Some(input) => input.read_line(&mut line),
+        None => io::stdin().read_line(&mut line),
+    };
+    match read.map_err(ErrorKind::Io)? {
+        0 => Ok(Value::Nil),
+        _ => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            Ok(Value::String(Symbol::new(line)))
+        }
+    }
+}
+
+fn binary(
+    operator: BinaryOp,
+    left: &Value,
+    right: &Value,
+    division_by_zero: DivisionByZero,
+) -> Result<Value, Error> {
+    let result = match (operator, left, right) {
+        (BinaryOp::Equal, left, right) => Some(Value::Boolean(left == right)),
+        (BinaryOp::NotEqual, left, right) => Some(Value::Boolean(left != right)),
+        (BinaryOp::Add, Value::String(left), Value::String(right)) => {
+            Some(Value::String(Symbol::new(&format!("{left}{right}"))))
+        }
+        (BinaryOp::Divide | BinaryOp::Remainder, Value::Number(_), Value::Number(right))
+            if *right == 0.0 && division_by_zero == DivisionByZero::Error =>
+        {
+            return Err(ErrorKind::DivisionByZero.into());
+        }
+        (operator, Value::Number(left), Value::Number(right)) => {
+            let (left, right) = (*left, *right);
+            Some(match operator {
+                BinaryOp::Greater => Value::Boolean(left > right),
+                BinaryOp::GreaterEqual => Value::Boolean(left >= right),
+                BinaryOp::Less => Value::Boolean(left < right),
+                BinaryOp::LessEqual => Value::Boolean(left <= right),
+                BinaryOp::Add => Value::Number(left + right),
+                BinaryOp::Subtract => Value::Number(left - right),
+                BinaryOp::Multiply => Value::Number(left * right),
+                BinaryOp::Divide => Value::Number(left / right),
+                BinaryOp::Remainder => Value::Number(left % right),
+                BinaryOp::Equal | BinaryOp::NotEqual => unreachable!("handled above"),
+            })
+        }
+        _ => None,
+    };
+    result.ok_or_else(|| {
+        ErrorKind::Operands {
+            operator,
+            left: left.type_name(),
+            right: right.type_name(),
+        }
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::compile,
+        ast::Expr,
+        parser, scanner,
+        unstable::{lower, resolve::resolve},
+    };
+
+    /// Runs `source` in a new interpreter, returning what it printed.
+    fn output(source: &str) -> String {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::with_output(output.clone());
+        let program = compile(source.as_bytes()).unwrap();
+        interpreter.interpret(&program).unwrap();
+        output.contents()
+    }
+
+    /// Runs `source`, which must fail, returning the runtime error.
+    fn error(source: &str) -> Error {
+        let mut interpreter = Interpreter::with_output(OutputBuffer::new());
+        let program = compile(source.as_bytes()).unwrap();
+        interpreter.interpret(&program).unwrap_err()
+    }
+
+    #[test]
+    fn evaluates_arithmetic_and_comparisons() {
+        assert_eq!(
+            output("print 1 + 2 * 3; print (1 + 2) * 3; print 7 % 4; print -(2 - 5) / 2;"),
+            "7\n9\n3\n1.5\n"
+        );
+        assert_eq!(
+            output("print 1 < 2; print 2 <= 1; print 3 > 3; print 3 >= 3;"),
+            "true\nfalse\nfalse\ntrue\n"
+        );
+        assert_eq!(output("print \"a\" + \"b\";"), "ab\n");
+    }
+
+    #[test]
+    fn evaluates_equality_and_negation() {
+        assert_eq!(
+            output("print 1 == 1; print \"a\" != \"a\"; print nil == false; print 1 == \"1\";"),
+            "true\nfalse\nfalse\nfalse\n"
+        );
+        assert_eq!(
+            output("print !nil; print !0; print !!\"\";"),
+            "true\nfalse\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn logical_operators_short_circuit_and_return_an_operand() {
+        assert_eq!(
+            output("print nil or \"x\"; print 1 and 2; print false and undefined; print 1 or undefined;"),
+            "x\n2\nfalse\n1\n"
+        );
+    }
+
+    #[test]
+    fn ord_and_chr_convert_between_characters_and_code_points() {
+        assert_eq!(output("print ord('a'); print ord('é');"), "97\n233\n");
+        assert_eq!(output("print chr(97) + chr(128512);"), "a😀\n");
+        assert_eq!(output("print chr(ord('z'));"), "z\n");
+    }
+
+    #[test]
+    fn ord_and_chr_reject_what_is_not_a_character() {
+        assert_eq!(
+            error("ord(\"ab\");").kind.to_string(),
+            "ord() expects a string of one character."
+        );
+        assert_eq!(error("ord(1);").line, Some(1));
+        for code in ["-1", "1.5", "55296"] {
+            assert_eq!(
+                error(&format!("chr({code});")).kind.to_string(),
+                "chr() expects the code point of a Unicode character."
+            );
+        }
+    }
+
+    #[test]
+    fn runtime_errors_name_the_operator_operand_types_and_line() {
+        assert_eq!(
+            error("print -\"a\";").to_string(),
+            "Operand of '-' must be a number, but it is a string. [line 1]"
+        );
+        assert_eq!(
+            error("\n\nprint 1 + nil;").to_string(),
+            "Operands of '+' must be two numbers or two strings, \
+             but the left operand is a number and the right operand is nil. [line 3]"
+        );
+        assert_eq!(
+            error("print 1 < \"a\";").to_string(),
+            "Operands of '<' must be numbers, \
+             but the left operand is a number and the right operand is a string. [line 1]"
+        );
+        assert_eq!(error("\nprint x;").line, Some(2));
+    }
+
+    #[test]
+    fn prints_values_the_way_jlox_does() {
+        assert_eq!(
+            output("print nil; print true; print 1; print 1.5; print \"s\"; print 'c';"),
+            "nil\ntrue\n1\n1.5\ns\nc\n"
+        );
+        assert_eq!(
+            output(
+                "fun f() {} class A { m() {} }\n\
+                 print clock; print f; print A; print A(); print A().m;"
+            ),
+            "<native fn>\n<fn f>\nA\nA instance\n<fn m>\n"
+        );
+    }
+
+    #[test]
+    fn calls_must_pass_as_many_arguments_as_the_callee_takes() {
+        let arity = |source| error(source).to_string();
+        assert_eq!(
+            arity("fun f(a, b) {} f(1);"),
+            "Expected 2 arguments but got 1. [line 1]"
+        );
+        assert_eq!(
+            arity("class A { init(x) {} } A();"),
+            "Expected 1 arguments but got 0. [line 1]"
+        );
+        assert_eq!(
+            arity("class A {} A(1);"),
+            "Expected 0 arguments but got 1. [line 1]"
+        );
+        assert_eq!(
+            arity("clock(1);"),
+            "Expected 0 arguments but got 1. [line 1]"
+        );
+        assert_eq!(output("fun f(a, b) { print a + b; } f(1, 2);"), "3\n");
+    }
+
+    #[test]
+    fn collects_a_closure_that_captures_itself() {
+        let mut interpreter = Interpreter::with_output(OutputBuffer::new());
+        let program = compile(
+            b"fun make() { var f; fun g() { return f; } f = g; return g; }\n\
+              var g = make();",
+        )
+        .unwrap();
+        interpreter.interpret(&program).unwrap();
+        let Value::Function(function) = interpreter.global_cell("g").unwrap().get() else {
+            panic!("g is not a function");
+        };
+        let closure = Rc::downgrade(&function.0);
+        drop(function);
+        interpreter
+            .interpret(&compile(b"g = nil;").unwrap())
+            .unwrap();
+        assert!(closure.upgrade().is_some(), "the cycle keeps it alive");
+        interpreter.collect_garbage();
+        assert!(closure.upgrade().is_none());
+    }
+
+    #[test]
+    fn collecting_before_every_allocation_keeps_what_is_in_use() {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::builder()
+            .output(output.clone())
+            .gc_stress(true)
+            .build()
+            .unwrap();
+        let program = compile(
+            b"class Counter { init() { this.n = 0; } add() { this.n = this.n + 1; return this; } }\n\
+              fun counter() { var c = Counter(); fun next() { return c.add().n; } return next; }\n\
+              var next = counter();\n\
+              for (var i = 0; i < 3; i = i + 1) print next();",
+        )
+        .unwrap();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(output.contents(), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn instances_store_fields() {
+        assert_eq!(
+            output(
+                "class A {} var a = A(); var b = A();\n\
+                 a.x = 1; b.x = 2; a.x = a.x + 10;\n\
+                 print a.x; print b.x; print a.y = 3;"
+            ),
+            "11\n2\n3\n"
+        );
+    }
+
+    #[test]
+    fn property_access_needs_an_instance_with_the_property() {
+        assert_eq!(
+            error("class A {}\nprint A().x;").to_string(),
+            "Undefined property 'x'. [line 2]"
+        );
+        assert_eq!(
+            error("print 1 .x;").to_string(),
+            "Only instances, classes and lists have properties. [line 1]"
+        );
+        assert_eq!(
+            error("\"s\".x = 2;").to_string(),
+            "Only instances and classes have fields. [line 1]"
+        );
+    }
+
+    #[test]
+    fn methods_stay_bound_to_their_instance() {
+        assert_eq!(
+            output(
+                "class A { init(n) { this.n = n; } get() { return this.n; } }\n\
+                 var get = A(1).get; var b = A(2); b.other = get;\n\
+                 print get(); print b.other();"
+            ),
+            "1\n1\n"
+        );
+    }
+
+    #[test]
+    fn fields_shadow_methods() {
+        assert_eq!(
+            output("class A { get() { return 1; } } var a = A(); a.get = 5; print a.get;"),
+            "5\n"
+        );
+    }
+
+    #[test]
+    fn binary_operators_call_methods_of_the_left_operand() {
+        assert_eq!(
+            output(
+                "class P {\n\
+                   init(x) { this.x = x; }\n\
+                   plus(o) { return P(this.x + o.x); }\n\
+                   eq(o) { return this.x == o.x; }\n\
+                   lt(o) { return this.x < o.x; }\n\
+                 }\n\
+                 print (P(1) + P(2)).x; print P(1) == P(1); print P(1) != P(1);\n\
+                 print P(1) < P(2);"
+            ),
+            "3\ntrue\nfalse\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn instances_without_operator_methods_keep_the_default_behaviour() {
+        assert_eq!(
+            output("class Q {} var q = Q(); print q == q; print Q() == Q();"),
+            "true\nfalse\n"
+        );
+        assert_eq!(
+            error("class Q {} print Q() - Q();").to_string(),
+            "Operands of '-' must be numbers, \
+             but the left operand is an instance and the right operand is an instance. [line 1]"
+        );
+    }
+
+    #[test]
+    fn print_and_concatenation_use_to_string() {
+        assert_eq!(
+            output(
+                "class V { toString() { return \"v\"; } }\n\
+                 print V(); print \"a \" + V(); print V() + \" b\";"
+            ),
+            "v\na v\nv b\n"
+        );
+    }
+
+    #[test]
+    fn plus_takes_precedence_over_to_string() {
+        assert_eq!(
+            output(
+                "class V { toString() { return \"v\"; } plus(o) { return \"plus\"; } }\n\
+                 print V() + \"s\";"
+            ),
+            "plus\n"
+        );
+        assert_eq!(output("class A {} print A();"), "A instance\n");
+    }
+
+    #[test]
+    fn division_by_zero_follows_ieee_by_default() {
+        assert_eq!(
+            output("print 1 / 0; print -1 / 0; print 0 / 0 == 0 / 0;"),
+            "Infinity\n-Infinity\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn division_by_zero_can_be_an_error() {
+        let mut interpreter = Interpreter::builder()
+            .output(OutputBuffer::new())
+            .division_by_zero(DivisionByZero::Error)
+            .build()
+            .unwrap();
+        for source in ["print 1;\nprint 1 / 0;", "print 1;\nprint 5 % 0;"] {
+            let program = compile(source.as_bytes()).unwrap();
+            let error = interpreter.interpret(&program).unwrap_err();
+            assert_eq!(error.to_string(), "Division by zero. [line 2]");
+        }
+        let program = compile(b"print 1 / 2;").unwrap();
+        assert!(interpreter.interpret(&program).is_ok());
+    }
+
+    #[test]
+    fn prints_numbers_the_way_jlox_does() {
+        assert_eq!(
+            output(
+                "print 1; print -0; print 2.5; print 0.1 + 0.2; print 1 / 3;\n\
+                 print 1234567; print 10000000; print 123456789012;\n\
+                 print 0.001; print 0.00001234;"
+            ),
+            "1\n-0\n2.5\n0.30000000000000004\n0.3333333333333333\n\
+             1234567\n1.0E7\n1.23456789012E11\n\
+             0.001\n1.234E-5\n"
+        );
+    }
+
+    #[test]
+    fn catch_receives_what_was_thrown() {
+        assert_eq!(
+            output(
+                "fun f() { throw \"boom\"; }\n\
+                 try { f(); print \"not reached\"; } catch (e) { print e; }\n\
+                 try { print 1; } catch (e) { print \"not reached\"; }"
+            ),
+            "boom\n1\n"
+        );
+    }
+
+    #[test]
+    fn catch_receives_runtime_errors_as_error_instances() {
+        assert_eq!(
+            output("try {\n  print nil.x;\n} catch (e) {\n  print e.message; print e.line;\n}"),
+            "Only instances, classes and lists have properties.\n2\n"
+        );
+    }
+
+    #[test]
+    fn finally_always_runs() {
+        assert_eq!(
+            output(
+                "fun f() { try { return 1; } finally { print \"finally\"; } }\n\
+                 print f();\n\
+                 try { try { throw 2; } finally { print \"inner\"; } } catch (e) { print e; }"
+            ),
+            "finally\n1\ninner\n2\n"
+        );
+    }
+
+    #[test]
+    fn uncaught_exceptions_are_errors() {
+        assert_eq!(
+            error("\nthrow 42;").to_string(),
+            "Uncaught exception: 42 [line 2]"
+        );
+    }
+
+    #[test]
+    fn assert_fails_on_a_falsey_condition() {
+        assert_eq!(
+            output("assert 1 < 2; assert \"\", \"unused\"; print 1;"),
+            "1\n"
+        );
+        assert_eq!(
+            error("\nassert 1 > 2;").to_string(),
+            "Assertion failed. [line 2]"
+        );
+        assert_eq!(
+            error("var x = 3;\nassert x == 4, \"x is not 4\";").to_string(),
+            "Assertion failed: x is not 4 [line 2]"
+        );
+        assert_eq!(
+            error("assert false, 3;").to_string(),
+            "Assertion failed: 3 [line 1]"
+        );
+    }
+
+    #[test]
+    fn tuples_are_values() {
+        assert_eq!(
+            output(
+                "var t = (1, \"a\", nil); print t; print (1,);\n\
+                 print (1, 2) == (1, 2); print (1, 2) == (2, 1);"
+            ),
+            "(1, a, nil)\n(1,)\ntrue\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn tuples_destructure_into_variables() {
+        assert_eq!(
+            output("var (a, b) = (1, 2); (a, b) = (b, a); print a; print b;"),
+            "2\n1\n"
+        );
+        assert_eq!(
+            error("var (a, b) = (1, 2, 3);").to_string(),
+            "Expected a tuple of 2 elements but got 3. [line 1]"
+        );
+        assert_eq!(
+            error("var (a, b) = 1;").to_string(),
+            "Can only destructure tuples, not a number. [line 1]"
+        );
+    }
+
+    #[test]
+    fn rest_parameters_collect_extra_arguments_into_a_list() {
+        assert_eq!(
+            output(
+                "fun f(a, ...rest) { print a; print rest; print rest.length; }\n\
+                 f(1); f(1, 2, 3);"
+            ),
+            "1\n[]\n0\n1\n[2, 3]\n2\n"
+        );
+        assert_eq!(
+            output("fun f(...xs) { xs.push(9); return xs.get(0); } print f();"),
+            "9\n"
+        );
+        assert_eq!(
+            error("fun f(a, ...rest) {} f();").to_string(),
+            "Expected at least 1 arguments but got 0. [line 1]"
+        );
+    }
+
+    #[test]
+    fn classes_hold_fields_of_their_own() {
+        assert_eq!(
+            output(
+                "class Config { debug() { return Config.level > 1; } }\n\
+                 Config.level = 1; print Config.level;\n\
+                 Config.level = Config.level + 1; print Config().debug();"
+            ),
+            "1\ntrue\n"
+        );
+        assert_eq!(
+            error("class A {}\nprint A.x;").to_string(),
+            "Undefined property 'x'. [line 2]"
+        );
+    }
+
+    #[test]
+    fn field_initializers_run_for_each_new_instance() {
+        assert_eq!(
+            output(
+                "var k = 0; class A { x = 1; y = k; }\n\
+                 var a = A(); k = 5; var b = A(); a.x = 3;\n\
+                 print a.y; print b.y; print A().x;"
+            ),
+            "0\n5\n1\n"
+        );
+    }
+
+    #[test]
+    fn field_initializers_run_before_init_and_after_the_superclass_ones() {
+        assert_eq!(
+            output(
+                "class A { x = 1; }\n\
+                 class B < A { y = this.x + 1; init() { print this.y; this.y = 3; } }\n\
+                 print B().y;"
+            ),
+            "2\n3\n"
+        );
+    }
+
+    #[test]
+    fn read_line_reads_from_the_given_input() {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::builder()
+            .output(output.clone())
+            .input(&b"first\r\nsecond\n"[..])
+            .build()
+            .unwrap();
+        let program = compile(b"print readLine(); print readLine(); print readLine();").unwrap();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(output.contents(), "first\nsecond\nnil\n");
+    }
+
+    #[test]
+    fn tail_calls_do_not_count_towards_the_call_depth() {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::builder()
+            .output(output.clone())
+            .max_call_depth(100)
+            .build()
+            .unwrap();
+        let source = include_str!("../../examples/tail_calls/count.lox");
+        interpreter
+            .interpret(&compile(source.as_bytes()).unwrap())
+            .unwrap();
+        assert_eq!(output.contents(), "1000000\n");
+
+        let program = compile(
+            b"fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); }\n\
+                                print count(1000);",
+        )
+        .unwrap();
+        let error = interpreter.interpret(&program).unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::StackOverflow(_)));
+    }
+
+    #[test]
+    fn labeled_jumps_leave_the_loop_they_name() {
+        assert_eq!(
+            output(
+                "outer: for (var i = 0; i < 3; i = i + 1) {\n\
+                   for (var j = 0; j < 3; j = j + 1) {\n\
+                     if (j == 1) continue outer;\n\
+                     if (i == 2) break outer;\n\
+                     print i * 10 + j;\n\
+                   }\n\
+                   print \"unreachable\";\n\
+                 }\n\
+                 print \"done\";"
+            ),
+            "0\n10\ndone\n"
+        );
+    }
+
+    #[test]
+    fn a_reused_label_names_the_innermost_loop() {
+        assert_eq!(
+            output(
+                "var i = 0;\n\
+                 a: while (i < 2) {\n\
+                   i = i + 1;\n\
+                   a: while (true) { break a; }\n\
+                   print i;\n\
+                 }"
+            ),
+            "1\n2\n"
+        );
+    }
+
+    #[test]
+    fn closures_share_their_declaration() {
+        let mut interpreter = Interpreter::with_output(OutputBuffer::new());
+        let program =
+            compile(b"fun make() { fun f() {} return f; } var a = make(); var b = make();");
+        interpreter.interpret(&program.unwrap()).unwrap();
+        let function = |name| match interpreter.global_cell(name).unwrap().get() {
+            Value::Function(function) => function,
+            value => panic!("not a function: {value}"),
+        };
+        let (a, b) = (function("a"), function("b"));
+        assert!(Rc::ptr_eq(&a.0.declaration, &b.0.declaration));
+        assert!(a != b);
+    }
+
+    #[test]
+    fn closures_capture_variables_by_reference() {
+        assert_eq!(
+            output(
+                "fun counter() {\n\
+                   var n = 0;\n\
+                   fun get() { return n; }\n\
+                   fun add() { n = n + 1; }\n\
+                   n = 10;\n\
+                   return (get, add);\n\
+                 }\n\
+                 var (get, add) = counter();\n\
+                 add(); add(); print get();"
+            ),
+            "12\n"
+        );
+    }
+
+    #[test]
+    fn locals_resolve_to_a_slot_in_their_scope() {
+        let program = compile(b"fun f(a, b) { var c = a; { var d = b; return c + d; } }").unwrap();
+        let binding = |name: &str| {
+            let (id, _) = program
+                .exprs()
+                .find(|(_, expr)| matches!(&expr.kind, ExprKind::Variable(n) if *n.symbol == *name))
+                .unwrap();
+            program.binding(id).unwrap()
+        };
+        assert_eq!(binding("a"), Binding::Local { depth: 0, slot: 0 });
+        assert_eq!(binding("b"), Binding::Local { depth: 1, slot: 1 });
+        assert_eq!(binding("c"), Binding::Local { depth: 1, slot: 2 });
+        assert_eq!(binding("d"), Binding::Local { depth: 0, slot: 0 });
+    }
+
+    #[test]
+    fn a_redeclared_local_replaces_the_variable_in_its_scope() {
+        let source = "{ var a = 1; fun f() { return a; } var a = 2; print f(); a = 3; print f(); }";
+        assert_eq!(output(source), "2\n3\n");
+        // Without a resolution, the interpreter finds the same variables by
+        // name.
+        let mut program = parser::parse(scanner::scan_tokens(source.as_bytes()).unwrap()).unwrap();
+        lower::lower(&mut program);
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::with_output(output.clone());
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(output.contents(), "2\n3\n");
+    }
+
+    #[test]
+    fn global_cells_are_shared_with_scripts() {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::with_output(output.clone());
+        assert!(interpreter.global_cell("x").is_none());
+        let program = compile(b"var x = 1; fun show() { print x; }").unwrap();
+        interpreter.interpret(&program).unwrap();
+
+        let cell = interpreter.global_cell("x").unwrap();
+        assert_eq!(cell, interpreter.global_cell("x").unwrap());
+        assert!(matches!(cell.get(), Value::Number(n) if n == 1.0));
+        cell.set(Value::Number(2.0));
+        interpreter
+            .interpret(&compile(b"show(); x = 3;").unwrap())
+            .unwrap();
+        assert_eq!(output.contents(), "2\n");
+        assert!(matches!(cell.get(), Value::Number(n) if n == 3.0));
+    }
+
+    #[test]
+    fn expect_eq_says_how_values_differ() {
+        assert_eq!(
+            output("expectEq(1, 1); expectEq((1, \"a\"), (1, \"a\")); print 1;"),
+            "1\n"
+        );
+        assert_eq!(
+            error("expectEq(\"abc\", \"abd\");").to_string(),
+            "Expectation failed: strings differ from character 2:\n  \
+             left:  \"abc\"\n  \
+             right: \"abd\" [line 1]"
+        );
+        assert_eq!(
+            error("expectEq(1, \"1\");").to_string(),
+            "Expectation failed: left is a number, right is a string:\n  \
+             left:  1\n  \
+             right: \"1\" [line 1]"
+        );
+        assert_eq!(
+            error("expectEq((1, 2), (1, 3));").to_string(),
+            "Expectation failed: values differ:\n  left:  (1, 2)\n  right: (1, 3) [line 1]"
+        );
+    }
+
+    #[test]
+    fn nesting_too_deep_to_parse_is_an_error() {
+        let sources = [
+            format!("print {}1{};", "(".repeat(10_000), ")".repeat(10_000)),
+            format!("print {}1;", "!".repeat(10_000)),
+            "{".repeat(10_000),
+            "fun f() {".repeat(10_000),
+        ];
+        for source in sources {
+            let error = compile(source.as_bytes()).unwrap_err();
+            assert!(error
+                .to_string()
+                .contains("Nesting is deeper than 256 levels."));
+        }
+    }
+
+    #[test]
+    fn deeply_nested_trees_resolve_and_run() {
+        // Deeper than the parser allows, so built by hand.
+        let mut program = compile(b"1").unwrap();
+        let mut expr = program.result.unwrap();
+        for _ in 0..100_000 {
+            let span = program[expr].span;
+            let kind = ExprKind::Unary {
+                operator: UnaryOp::Negate,
+                right: expr,
+            };
+            expr = program.add_expr(Expr { kind, span });
+        }
+        program.result = Some(expr);
+        resolve(&mut program);
+        let mut interpreter = Interpreter::with_output(OutputBuffer::new());
+        let value = interpreter.interpret(&program).unwrap();
+        assert_eq!(value, Some(Value::Number(1.0)));
+    }
+
+    /// Repeats a string multiplied by a number and leaves everything else
+    /// to the interpreter.
+    struct Repeat;
+
+    impl Operators for Repeat {
+        fn binary(
+            &self,
+            operator: BinaryOp,
+            left: &Value,
+            right: &Value,
+        ) -> Option<Result<Value, Error>> {
+            match (operator, left, right) {
+                (BinaryOp::Multiply, Value::String(string), Value::Number(count)) => {
+                    let repeated = string.repeat(*count as usize);
+                    Some(Ok(Value::String(Symbol::new(&repeated))))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn an_operators_hook_overrides_what_it_handles() {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::builder()
+            .output(output.clone())
+            .operators(Repeat)
+            .build()
+            .unwrap();
+        let program = compile(b"print \"ab\" * 3; print 2 * 3; print \"a\" + \"b\";").unwrap();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(output.contents(), "ababab\n6\nab\n");
+        let program = compile(b"print 3 * \"ab\";").unwrap();
+        let error = interpreter.interpret(&program).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Operands of '*' must be numbers, but the left operand is a number and the right \
+             operand is a string. [line 1]"
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Lowers surface syntax to the core language the interpreter runs. The
+//! parser keeps constructs such as `for` loops as written, so that tools
+//! see the program as its author did; this pass rewrites each of them in
+//! terms of simpler statements.
+//!
+//! Only `for` loops need lowering today. Further conveniences belong here
+//! too, rather than in the interpreter.
+
+use std::rc::Rc;
+
+use crate::{
+    ast::{Expr, ExprId, ExprKind, Literal, Program, Statement, StatementKind},
+    scanner::{Span, Token, TokenType},
+    unstable::stack,
+};
+
+/// Rewrites every piece of surface syntax in `program` in place. Nodes
+/// introduced by the rewriting span the construct they replace.
+pub fn lower(program: &mut Program) {
+    let mut statements = std::mem::take(&mut program.statements);
+    for statement in &mut statements {
+        lower_statement(program, statement);
+    }
+    program.statements = statements;
+}
+
+fn lower_statement(program: &mut Program, statement: &mut Statement) {
+    // Trees built by hand can nest deeper than the parser allows.
+    stack::grow(|| lower_nested(program, statement));
+}
+
+fn lower_nested(program: &mut Program, statement: &mut Statement) {
+    match &mut statement.kind {
+        StatementKind::Block(statements) => {
+            for statement in statements {
+                lower_statement(program, statement);
+            }
+        }
+        StatementKind::Class { methods, .. } => {
+            // Fresh from the parser, the methods are not shared yet.
+            for statement in methods
+                .iter_mut()
+                .flat_map(|method| &mut Rc::make_mut(method).body)
+            {
+                lower_statement(program, statement);
+            }
+        }
+        StatementKind::For {
+            initializer, body, ..
+        } => {
+            if let Some(initializer) = initializer {
+                lower_statement(program, initializer);
+            }
+            lower_statement(program, body);
+        }
+        StatementKind::Function(function) => {
+            for statement in &mut Rc::make_mut(function).body {
+                lower_statement(program, statement);
+            }
+        }
+        StatementKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            lower_statement(program, then_branch);
+            if let Some(else_branch) = else_branch {
+                lower_statement(program, else_branch);
+            }
+        }
+        StatementKind::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            lower_statement(program, body);
+            if let Some(catch) = catch {
+                lower_statement(program, &mut catch.body);
+            }
+            if let Some(finally) = finally {
+                lower_statement(program, finally);
+            }
+        }
+        StatementKind::While { body, .. } => lower_statement(program, body),
+        StatementKind::Assert { .. }
+        | StatementKind::Break { .. }
+        | StatementKind::Continue { .. }
+        | StatementKind::Expression(_)
+        | StatementKind::Print(_)
+        | StatementKind::Return { .. }
+        | StatementKind::Throw { .. }
+        | StatementKind::Var { .. }
+        | StatementKind::VarTuple { .. } => {}
+    }
+    let kind = std::mem::replace(&mut statement.kind, StatementKind::Block(Vec::new()));
+    statement.kind = match kind {
+        StatementKind::For {
+            initializer,
+            condition,
+            increment,
+            body,
+            label,
+        } => lower_for(
+            program,
+            statement.span,
+            initializer,
+            condition,
+            increment,
+            body,
+            label,
+        ),
+        kind => kind,
+    };
+}
+
+/// Turns `for (initializer; condition; increment) body` into
+/// `{ initializer; while (condition) body }`, with the increment kept on
+/// the `while` loop so that `continue` still runs it.
+fn lower_for(
+    program: &mut Program,
+    span: Span,
+    initializer: Option<Box<Statement>>,
+    condition: Option<ExprId>,
+    increment: Option<ExprId>,
+    body: Box<Statement>,
+    label: Option<Token>,
+) -> StatementKind {
+    // A missing condition is always true. It has no text of its own, so
+    // it takes the position of the `for` keyword.
+    let condition = condition.unwrap_or_else(|| {
+        let keyword = Span {
+            end: span.start + "for".len(),
+            ..span
+        };
+        let token = Token {
+            token_type: TokenType::True,
+            lexeme: "true".to_owned(),
+            span: keyword,
+            doc: None,
+        };
+        program.add_expr(Expr {
+            kind: ExprKind::Literal(Literal::new(token)),
+            span: keyword,
+        })
+    });
+    let kind = StatementKind::While {
+        condition,
+        body,
+        increment,
+        label,
+    };
+    match initializer {
+        Some(initializer) => {
+            let while_loop = Statement {
+                id: program.next_statement_id(),
+                kind,
+                span,
+            };
+            StatementKind::Block(vec![*initializer, while_loop])
+        }
+        None => kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::compile,
+        interpreter::{Interpreter, OutputBuffer},
+        parser::parse,
+        scanner::scan_tokens,
+        unstable::ast_printer::print_statement,
+    };
+
+    /// The s-expression for each statement in `source` once lowered.
+    fn lowered(source: &str) -> Vec<String> {
+        let mut program = parse(scan_tokens(source.as_bytes()).unwrap()).unwrap();
+        lower(&mut program);
+        program
+            .statements
+            .iter()
+            .map(|statement| print_statement(&program, statement))
+            .collect()
+    }
+
+    #[test]
+    fn for_loops_become_while_loops_in_a_block() {
+        assert_eq!(
+            lowered("for (var i = 0; i < 3; i = i + 1) print i;"),
+            ["(block (var i 0) (while (< i 3) (print i) (= i (+ i 1))))"]
+        );
+        assert_eq!(
+            lowered("for (; i < 3;) print i;"),
+            ["(while (< i 3) (print i))"]
+        );
+        assert_eq!(
+            lowered("outer: for (;;) {}"),
+            ["(outer: (while true (block)))"]
+        );
+    }
+
+    /// What running `source` prints.
+    fn output(source: &str) -> String {
+        let output = OutputBuffer::new();
+        let mut interpreter = Interpreter::with_output(output.clone());
+        let program = compile(source.as_bytes()).unwrap();
+        interpreter.interpret(&program).unwrap();
+        output.contents()
+    }
+
+    #[test]
+    fn continue_in_a_lowered_for_loop_runs_the_increment() {
+        assert_eq!(
+            output("for (var i = 0; i < 4; i = i + 1) { if (i == 1) continue; print i; }"),
+            "0\n2\n3\n"
+        );
+    }
+
+    #[test]
+    fn the_initializer_of_a_lowered_for_loop_is_scoped_to_it() {
+        assert_eq!(
+            output("var i = \"outer\"; for (var i = 0; i < 2; i = i + 1) print i; print i;"),
+            "0\n1\nouter\n"
+        );
+    }
+
+    #[test]
+    fn lowers_deeply_nested_trees() {
+        // Deeper than the parser allows, so built by hand.
+        let mut program = parse(scan_tokens(b"for (;;) {}").unwrap()).unwrap();
+        let mut statement = program.statements.pop().unwrap();
+        for _ in 0..100_000 {
+            let span = statement.span;
+            statement = Statement {
+                id: program.next_statement_id(),
+                kind: StatementKind::Block(vec![statement]),
+                span,
+            };
+        }
+        program.statements.push(statement);
+        lower(&mut program);
+        // Take the tree apart a level at a time, since dropping it whole
+        // would recurse as deeply as it nests.
+        let mut statement = program.statements.pop().unwrap();
+        while let StatementKind::Block(mut statements) = statement.kind {
+            statement = statements.pop().unwrap();
+        }
+        assert!(matches!(statement.kind, StatementKind::While { .. }));
+    }
+}
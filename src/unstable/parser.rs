@@ -0,0 +1,1918 @@
+use std::{error, fmt, ops::Range, rc::Rc};
+
+use crate::{
+    ast::{
+        BinaryOp, Catch, Comment, CommentPlacement, Expr, ExprId, ExprKind, Field, Function,
+        Literal, LogicalOp, Name, Program, Statement, StatementId, StatementKind, UnaryOp,
+    },
+    scanner::{Span, Token, TokenType},
+    unstable::{stack, validate},
+};
+
+/// The most arguments a call may pass, and the most parameters a function
+/// may declare.
+pub const MAX_ARITY: usize = 255;
+
+/// How deeply expressions and statements may nest. Parsing recurses once
+/// per level, so unbounded nesting would overflow the stack. A chain of
+/// operators, calls or property accesses, like `a + b + c`, nests a level
+/// per link: the passes after parsing recurse through it just the same.
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A token of a particular kind was required, e.g. `')' after expression`.
+    UnexpectedToken {
+        found: Token,
+        expected: &'static str,
+    },
+    /// `found` cannot start an expression.
+    ExpectedExpression {
+        found: Token,
+    },
+    /// The left-hand side of `=` is not something that can be assigned to.
+    InvalidAssignmentTarget {
+        equals: Token,
+    },
+    /// Like [`InvalidAssignmentTarget`](Self::InvalidAssignmentTarget), but
+    /// in the condition of an `if` or loop, where `==` was probably meant.
+    EqualsInCondition {
+        equals: Token,
+    },
+    /// A call passes more than [`MAX_ARITY`] arguments; `found` is the
+    /// first argument over the limit.
+    TooManyArguments {
+        found: Token,
+    },
+    /// A function declares more than [`MAX_ARITY`] parameters; `found` is
+    /// the first parameter over the limit.
+    TooManyParameters {
+        found: Token,
+    },
+    /// Expressions or statements nest more than [`MAX_DEPTH`] levels deep
+    /// at `found`. Parsing stops here; nothing after it is checked.
+    TooDeep {
+        found: Token,
+    },
+    /// A literal expression holds `found`, which is not a literal token.
+    /// Only trees built by hand can have one; see
+    /// [`validate`](crate::unstable::validate::validate).
+    NotALiteral {
+        found: Token,
+    },
+    ThisOutsideClass {
+        keyword: Token,
+    },
+    SuperOutsideClass {
+        keyword: Token,
+    },
+    /// `super` in a class that does not inherit from another.
+    SuperWithoutSuperclass {
+        keyword: Token,
+    },
+    ReturnOutsideFunction {
+        keyword: Token,
+    },
+    /// `return` with a value in an `init` method.
+    ReturnFromInitializer {
+        keyword: Token,
+    },
+    /// `break` or `continue` that is not inside a loop.
+    OutsideLoop {
+        keyword: Token,
+    },
+    /// `break` or `continue` naming a label that no enclosing loop has.
+    UnknownLabel {
+        label: Token,
+    },
+}
+
+impl ParseError {
+    /// The token at which the error was detected.
+    pub fn token(&self) -> &Token {
+        match self {
+            Self::UnexpectedToken { found, .. }
+            | Self::ExpectedExpression { found }
+            | Self::TooManyArguments { found }
+            | Self::TooManyParameters { found }
+            | Self::TooDeep { found }
+            | Self::NotALiteral { found } => found,
+            Self::ThisOutsideClass { keyword }
+            | Self::SuperOutsideClass { keyword }
+            | Self::SuperWithoutSuperclass { keyword }
+            | Self::ReturnOutsideFunction { keyword }
+            | Self::ReturnFromInitializer { keyword }
+            | Self::OutsideLoop { keyword } => keyword,
+            Self::UnknownLabel { label } => label,
+            Self::InvalidAssignmentTarget { equals } | Self::EqualsInCondition { equals } => equals,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = self.token();
+        write!(f, "[line {}:{}] Error", token.span.line, token.span.column)?;
+        match token.token_type {
+            TokenType::Eof => write!(f, " at end: ")?,
+            _ => write!(f, " at '{}': ", token.lexeme)?,
+        }
+        match self {
+            Self::UnexpectedToken { expected, .. } => write!(f, "Expected {expected}."),
+            Self::ExpectedExpression { .. } => write!(f, "Expected expression."),
+            Self::InvalidAssignmentTarget { .. } => write!(f, "Invalid assignment target."),
+            Self::EqualsInCondition { .. } => {
+                write!(f, "Invalid assignment target; did you mean '=='?")
+            }
+            Self::TooManyArguments { .. } => {
+                write!(f, "Can't have more than {MAX_ARITY} arguments.")
+            }
+            Self::TooManyParameters { .. } => {
+                write!(f, "Can't have more than {MAX_ARITY} parameters.")
+            }
+            Self::TooDeep { .. } => write!(f, "Nesting is deeper than {MAX_DEPTH} levels."),
+            Self::NotALiteral { .. } => write!(f, "Expected a literal."),
+            Self::ThisOutsideClass { .. } => write!(f, "Can't use 'this' outside of a class."),
+            Self::SuperOutsideClass { .. } => {
+                write!(f, "Can't use 'super' outside of a class.")
+            }
+            Self::SuperWithoutSuperclass { .. } => {
+                write!(f, "Can't use 'super' in a class with no superclass.")
+            }
+            Self::ReturnOutsideFunction { .. } => write!(f, "Can't return from top-level code."),
+            Self::ReturnFromInitializer { .. } => {
+                write!(f, "Can't return a value from an initializer.")
+            }
+            Self::OutsideLoop { keyword } => {
+                write!(f, "Can't use '{}' outside of a loop.", keyword.lexeme)
+            }
+            Self::UnknownLabel { .. } => write!(f, "No enclosing loop has this label."),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Something legal but probably not what the author meant. Warnings never
+/// stop a program from being parsed or run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// A `;` with no statement before it.
+    EmptyStatement { semicolon: Token },
+    /// Parentheses, opening at `paren`, that change nothing: around a
+    /// single value, another parenthesized expression, or a whole
+    /// expression that nothing else is part of.
+    RedundantParentheses { paren: Token },
+    /// An assignment, at `equals`, that makes up the whole condition of an
+    /// `if`, `while` or `for`: most likely a typo for `==`. Parentheses
+    /// around the assignment say it is meant.
+    AssignmentInCondition { equals: Token },
+    /// A local variable with the name of one in an enclosing scope, which
+    /// it hides. Found by the [resolver](crate::unstable::resolve).
+    ShadowedVariable { name: Token },
+    /// A local variable declared again in the same scope, which canonical
+    /// Lox rejects. Found by the [resolver](crate::unstable::resolve).
+    RedeclaredVariable { name: Token },
+}
+
+impl Warning {
+    /// The token at which the warning was detected.
+    pub fn token(&self) -> &Token {
+        match self {
+            Self::EmptyStatement { semicolon: token }
+            | Self::RedundantParentheses { paren: token }
+            | Self::AssignmentInCondition { equals: token }
+            | Self::ShadowedVariable { name: token }
+            | Self::RedeclaredVariable { name: token } => token,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = self.token();
+        write!(
+            f,
+            "[line {}:{}] Warning at '{}': ",
+            token.span.line, token.span.column, token.lexeme
+        )?;
+        match self {
+            Self::EmptyStatement { .. } => write!(f, "Empty statement."),
+            Self::RedundantParentheses { .. } => write!(f, "Redundant parentheses."),
+            Self::AssignmentInCondition { .. } => {
+                write!(f, "Assignment used as a condition; did you mean '=='?")
+            }
+            Self::ShadowedVariable { .. } => {
+                write!(
+                    f,
+                    "Shadows a variable of the same name in an enclosing scope."
+                )
+            }
+            Self::RedeclaredVariable { .. } => {
+                write!(f, "Already a variable with this name in this scope.")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseErrors {
+    errors: Vec<ParseError>,
+}
+
+impl ParseErrors {
+    pub(crate) fn new(errors: Vec<ParseError>) -> Self {
+        Self { errors }
+    }
+}
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, error) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ParseErrors {}
+
+/// Parses a script. The script may end with an expression that has no
+/// `;`, which becomes the program's [`result`](Program::result).
+pub fn parse(tokens: Vec<Token>) -> Result<Program, ParseErrors> {
+    let mut program = Program::default();
+    Parser::new(tokens, &mut program).parse()?;
+    Ok(program)
+}
+
+/// Like [`parse`], but also returns the [`Warning`]s found along the way,
+/// in source order.
+pub fn parse_with_warnings(tokens: Vec<Token>) -> Result<(Program, Vec<Warning>), ParseErrors> {
+    let mut program = Program::default();
+    let mut parser = Parser::new(tokens, &mut program);
+    parser.parse()?;
+    let mut warnings = parser.warnings;
+    warnings.sort_by_key(|warning| warning.token().span.start);
+    // `if ((x))` is found redundant both as a condition and as a grouping.
+    warnings.dedup();
+    Ok((program, warnings))
+}
+
+/// Parses `tokens` as a single expression, with no surrounding statement.
+/// The expression becomes the program's [`result`](Program::result), so
+/// interpreting the program evaluates it.
+pub fn parse_expression(tokens: Vec<Token>) -> Result<Program, ParseErrors> {
+    let mut program = Program::default();
+    let mut parser = Parser::new(tokens, &mut program);
+    let result = parser.expression().and_then(|expr| {
+        if parser.is_at_end() {
+            Ok(expr)
+        } else {
+            Err(ParseError::UnexpectedToken {
+                found: parser.peek().clone(),
+                expected: "end of expression",
+            })
+        }
+    });
+    let mut errors = parser.errors;
+    if let Ok(expr) = result {
+        if errors.is_empty() {
+            validate::check(&program, &[], Some(expr), &mut errors);
+        }
+    }
+    match result {
+        Ok(expr) if errors.is_empty() => {
+            program.result = Some(expr);
+            Ok(program)
+        }
+        Ok(_) => Err(ParseErrors { errors }),
+        Err(error) => {
+            errors.push(error);
+            Err(ParseErrors { errors })
+        }
+    }
+}
+
+/// Parses a line of REPL input, which follows the same grammar as a
+/// script. An expression statement that ends the input, such as `1 + 2;`,
+/// becomes the program's [`result`](Program::result) too, for the REPL to
+/// echo its value, unless it is an assignment.
+pub fn parse_repl(tokens: Vec<Token>) -> Result<Program, ParseErrors> {
+    let mut program = parse(tokens)?;
+    echo_last_expression(&mut program);
+    Ok(program)
+}
+
+fn echo_last_expression(program: &mut Program) {
+    if program.result.is_some() {
+        return;
+    }
+    let Some(Statement {
+        kind: StatementKind::Expression(expr),
+        ..
+    }) = program.statements.last()
+    else {
+        return;
+    };
+    if !matches!(
+        program[*expr].kind,
+        ExprKind::Assign { .. } | ExprKind::AssignTuple { .. } | ExprKind::Set { .. }
+    ) {
+        program.result = Some(*expr);
+        program.statements.pop();
+    }
+}
+
+/// Like [`parse`], but also describes how the parser got there: each
+/// grammar rule it applied, indented under the rule that applied it, with
+/// the tokens the rule consumed. A rule that failed is marked `(error)`.
+pub fn parse_traced(tokens: Vec<Token>) -> (Result<Program, ParseErrors>, String) {
+    let mut program = Program::default();
+    let mut parser = Parser::new(tokens, &mut program);
+    parser.trace = Some(Vec::new());
+    let result = parser.parse();
+    let mut trace = String::new();
+    for step in parser.trace.take().unwrap_or_default() {
+        trace.push_str(&format!(
+            "{:indent$}{}",
+            "",
+            step.rule,
+            indent = step.depth * 2
+        ));
+        let tokens = &parser.tokens[step.tokens];
+        if !tokens.is_empty() {
+            trace.push(':');
+            for token in tokens {
+                trace.push(' ');
+                trace.push_str(&token.lexeme);
+            }
+        }
+        if step.failed {
+            trace.push_str(" (error)");
+        }
+        trace.push('\n');
+    }
+    (result.map(|()| program), trace)
+}
+
+/// Like [`parse`], but keeps ordinary comments in [`Program::comments`],
+/// each attached to its nearest statement, so that tools which rewrite
+/// source can put them back. `tokens` should come from a scanner with
+/// [`keep_comments`](crate::scanner::ScannerOptions::keep_comments) set.
+pub fn parse_lossless(tokens: Vec<Token>) -> Result<Program, ParseErrors> {
+    let mut comments = Vec::new();
+    let mut code = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        match token.token_type {
+            TokenType::Comment => {
+                // A comment that shares a line with the code before it
+                // annotates that code; any other comment introduces what
+                // follows it.
+                let after = code
+                    .last()
+                    .filter(|previous: &&Token| previous.span.line == token.span.line)
+                    .map(|previous| previous.span.end);
+                let text = token.lexeme["//".len()..].to_owned();
+                comments.push((text, token.span, after));
+            }
+            _ => code.push(token),
+        }
+    }
+    let mut program = parse(code)?;
+    let statements = preorder(&program.statements);
+    for (text, span, after) in comments {
+        let (statement, placement) = attach(&program.statements, &statements, span, after);
+        program.comments.push(Comment {
+            text,
+            span,
+            statement,
+            placement,
+        });
+    }
+    Ok(program)
+}
+
+/// Finds the statement that a comment at `span` belongs to. `statements`
+/// lists every statement of the program in preorder, and `after` is the end
+/// of the code before the comment on the same line, if there is any.
+fn attach(
+    top_level: &[Statement],
+    statements: &[&Statement],
+    span: Span,
+    after: Option<usize>,
+) -> (Option<StatementId>, CommentPlacement) {
+    let trailing = after.and_then(|end| {
+        statements
+            .iter()
+            .find(|statement| statement.span.end == end)
+    });
+    if let Some(statement) = trailing {
+        return (Some(statement.id), CommentPlacement::Trailing);
+    }
+    // A comment inside a block or function body stays there. In preorder,
+    // the last statement enclosing the comment is the innermost one.
+    let container = statements
+        .iter()
+        .rev()
+        .find(|statement| statement.span.start < span.start && span.end < statement.span.end);
+    let siblings = match container {
+        Some(container) => container.children(),
+        None => top_level.iter().collect(),
+    };
+    let mut before = None;
+    for statement in siblings {
+        if statement.span.start >= span.end {
+            return (Some(statement.id), CommentPlacement::Leading);
+        }
+        if statement.span.end <= span.start {
+            before = Some(statement);
+        }
+    }
+    // Nothing follows the comment, so it ends its block or the program.
+    let statement = before.or(container.copied()).map(|statement| statement.id);
+    (statement, CommentPlacement::End)
+}
+
+/// Lists `statements` and everything nested in them, each statement before
+/// its children.
+fn preorder(statements: &[Statement]) -> Vec<&Statement> {
+    let mut ordered = Vec::new();
+    let mut pending: Vec<_> = statements.iter().rev().collect();
+    while let Some(statement) = pending.pop() {
+        ordered.push(statement);
+        pending.extend(statement.children().into_iter().rev());
+    }
+    ordered
+}
+
+/// Like [`parse_repl`], but parses into `program`, replacing its contents
+/// while reusing the memory they occupied. `tokens` is left empty with its
+/// capacity intact, ready for the scanner's next input.
+pub fn parse_repl_into(tokens: &mut Vec<Token>, program: &mut Program) -> Result<(), ParseErrors> {
+    program.clear();
+    let mut parser = Parser::new(std::mem::take(tokens), program);
+    let result = parser.parse();
+    *tokens = std::mem::take(&mut parser.tokens);
+    tokens.clear();
+    result?;
+    echo_last_expression(program);
+    Ok(())
+}
+
+/// Parses `tokens` for [`reparse`](crate::unstable::incremental::reparse),
+/// adding their expressions to `program` but returning the statements,
+/// and the result if `tokens` end the script, rather than storing them in
+/// it.
+pub(crate) fn parse_statements(
+    tokens: Vec<Token>,
+    program: &mut Program,
+    ends_script: bool,
+) -> Result<(Vec<Statement>, Option<ExprId>), ParseErrors> {
+    let previous = program.result.take();
+    let mut parser = Parser::new(tokens, program);
+    let mut statements = Vec::new();
+    while !parser.is_at_end() {
+        let statement = if ends_script {
+            parser.top_level_declaration()
+        } else {
+            parser.declaration()
+        };
+        statements.extend(statement);
+    }
+    let result = std::mem::replace(&mut parser.program.result, previous);
+    if parser.errors.is_empty() {
+        validate::check(parser.program, &statements, result, &mut parser.errors);
+    }
+    if parser.errors.is_empty() {
+        Ok((statements, result))
+    } else {
+        Err(ParseErrors {
+            errors: parser.errors,
+        })
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    current: usize,
+    errors: Vec<ParseError>,
+    warnings: Vec<Warning>,
+    program: &'a mut Program,
+    depth: usize,
+    /// Set once nesting gets too deep, after which the parser skips to the
+    /// end of the input and reports nothing further.
+    halted: bool,
+    /// The grammar rules applied so far, kept only by [`parse_traced`].
+    trace: Option<Vec<TraceStep>>,
+    /// How many traced rules are in progress.
+    trace_depth: usize,
+}
+
+struct TraceStep {
+    rule: &'static str,
+    depth: usize,
+    /// Indices of the tokens the rule consumed.
+    tokens: Range<usize>,
+    failed: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(mut tokens: Vec<Token>, program: &'a mut Program) -> Self {
+        // The scanner always ends its output with `Eof`, but callers may build
+        // token streams by hand. Lookahead relies on the `Eof` being there.
+        if !matches!(
+            tokens.last(),
+            Some(Token {
+                token_type: TokenType::Eof,
+                ..
+            })
+        ) {
+            let span = tokens.last().map_or(
+                Span {
+                    line: 1,
+                    column: 1,
+                    ..Span::default()
+                },
+                |last| Span {
+                    start: last.span.end,
+                    end: last.span.end,
+                    line: last.span.line,
+                    column: last.span.column + last.lexeme.len(),
+                },
+            );
+            tokens.push(Token {
+                token_type: TokenType::Eof,
+                lexeme: String::new(),
+                span,
+                doc: None,
+            });
+        }
+        Self {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            program,
+            depth: 0,
+            halted: false,
+            trace: None,
+            trace_depth: 0,
+        }
+    }
+
+    /// Parses a declaration, recording any error and skipping to the next
+    /// statement boundary so that parsing can continue after it.
+    fn parse(&mut self) -> Result<(), ParseErrors> {
+        while !self.is_at_end() {
+            if let Some(statement) = self.top_level_declaration() {
+                self.program.statements.push(statement);
+            }
+        }
+        // Checking what could not be parsed would only add confusing
+        // follow-on errors.
+        if self.errors.is_empty() {
+            let program = &*self.program;
+            validate::check(
+                program,
+                &program.statements,
+                program.result,
+                &mut self.errors,
+            );
+        }
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseErrors {
+                errors: std::mem::take(&mut self.errors),
+            })
+        }
+    }
+
+    /// Parses a top-level declaration, where an expression statement that
+    /// ends the input may omit its `;`.
+    fn top_level_declaration(&mut self) -> Option<Statement> {
+        match self.peek().token_type {
+            TokenType::Class
+            | TokenType::Fun
+            | TokenType::Var
+            | TokenType::Assert
+            | TokenType::For
+            | TokenType::If
+            | TokenType::Print
+            | TokenType::Return
+            | TokenType::Throw
+            | TokenType::Try
+            | TokenType::While
+            | TokenType::Break
+            | TokenType::Continue
+            | TokenType::LeftBrace
+            | TokenType::Semicolon => self.declaration(),
+            TokenType::Identifier if self.peek_next().token_type == TokenType::Colon => {
+                self.declaration()
+            }
+            _ => {
+                let result = self.traced("declaration", |parser| {
+                    parser.traced("statement", |parser| {
+                        parser.traced("exprStmt", |parser| {
+                            let expr = parser.whole_expression()?;
+                            if parser.is_at_end() {
+                                parser.program.result = Some(expr);
+                                return Ok(None);
+                            }
+                            parser.consume(TokenType::Semicolon, "';' after expression")?;
+                            Ok(Some(parser.new_statement(
+                                StatementKind::Expression(expr),
+                                parser.span_from(parser.program[expr].span),
+                            )))
+                        })
+                    })
+                });
+                self.recover(result).flatten()
+            }
+        }
+    }
+
+    fn declaration(&mut self) -> Option<Statement> {
+        let result = self.traced("declaration", |parser| {
+            if parser.matches(&[TokenType::Class]) {
+                parser.traced("classDecl", Self::class_declaration)
+            } else if parser.matches(&[TokenType::Fun]) {
+                parser.traced("funDecl", Self::function).map(|function| {
+                    let span = function.span;
+                    parser.new_statement(StatementKind::Function(Rc::new(function)), span)
+                })
+            } else if parser.matches(&[TokenType::Var]) {
+                parser.traced("varDecl", Self::var_declaration)
+            } else {
+                parser.statement()
+            }
+        });
+        self.recover(result)
+    }
+
+    /// Records a failed declaration's error and skips to where the next
+    /// declaration is likely to start.
+    fn recover<T>(&mut self, result: Result<T, ParseError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(_) if self.halted => None,
+            Err(error @ ParseError::TooDeep { .. }) => {
+                self.errors.push(error);
+                self.halted = true;
+                self.current = self.tokens.len() - 1;
+                None
+            }
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        let doc = self.previous().doc.clone();
+        let name = self.consume_name("class name")?;
+        let superclass = if self.matches(&[TokenType::Less]) {
+            let superclass = self.consume_name("superclass name")?;
+            let span = superclass.token.span;
+            Some(self.add_expr(ExprKind::Variable(superclass), span))
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, "'{' before class body")?;
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if self.check(&TokenType::Identifier) && self.peek_next().token_type == TokenType::Equal
+            {
+                fields.push(self.traced("field", Self::field)?);
+            } else {
+                methods.push(Rc::new(self.traced("function", Self::function)?));
+            }
+        }
+        self.consume(TokenType::RightBrace, "'}' after class body")?;
+        Ok(self.new_statement(
+            StatementKind::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+                doc,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /// Parses a field declaration in a class body, `name = initializer;`.
+    fn field(&mut self) -> Result<Field, ParseError> {
+        let name = self.consume_name("field name")?;
+        self.consume(TokenType::Equal, "'=' after field name")?;
+        let initializer = self.whole_expression()?;
+        self.consume(TokenType::Semicolon, "';' after field declaration")?;
+        Ok(Field { name, initializer })
+    }
+
+    /// Parses a function or method declaration. For functions, the `fun`
+    /// keyword has already been consumed and carries the documentation.
+    fn function(&mut self) -> Result<Function, ParseError> {
+        let (start, doc) = match self.previous().token_type {
+            TokenType::Fun => (self.previous().span, self.previous().doc.clone()),
+            _ => (self.peek().span, self.peek().doc.clone()),
+        };
+        let name = self.consume_name("function name")?;
+        self.consume(TokenType::LeftBracket, "'(' after function name")?;
+        let mut params = Vec::new();
+        let mut variadic = false;
+        // Checking for `)` before each parameter, rather than only before
+        // the first, is what permits a trailing comma.
+        while !self.check(&TokenType::RightBracket) {
+            if params.len() == MAX_ARITY {
+                // Reported without unwinding: the declaration is otherwise
+                // well-formed, so parsing carries on as normal.
+                let found = self.peek().clone();
+                self.errors.push(ParseError::TooManyParameters { found });
+            }
+            variadic = self.matches(&[TokenType::Ellipsis]);
+            params.push(self.consume_name("parameter name")?);
+            // A rest parameter comes last.
+            if variadic || !self.matches(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBracket, "')' after parameters")?;
+        self.consume(TokenType::LeftBrace, "'{' before function body")?;
+        let body = self.nested(|parser| parser.traced("block", Self::block))?;
+        Ok(Function {
+            name,
+            params,
+            variadic,
+            body,
+            doc,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses the rest of a tuple whose first element, `first`, and the
+    /// comma after it have been parsed. A comma may follow the last
+    /// element, and must when there is only one.
+    fn tuple(&mut self, first: ExprId) -> Result<Vec<ExprId>, ParseError> {
+        let mut elements = vec![first];
+        while !self.check(&TokenType::RightBracket) {
+            elements.push(self.expression()?);
+            if !self.matches(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBracket, "')' after tuple")?;
+        Ok(elements)
+    }
+
+    fn var_declaration(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        if self.matches(&[TokenType::LeftBracket]) {
+            return self.var_tuple_declaration(start);
+        }
+        let name = self.consume_name("variable name")?;
+        let initializer = if self.matches(&[TokenType::Equal]) {
+            Some(self.whole_expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::Semicolon, "';' after variable declaration")?;
+        Ok(self.new_statement(
+            StatementKind::Var { name, initializer },
+            self.span_from(start),
+        ))
+    }
+
+    /// Parses `var (a, b) = initializer;` from the names on, the `(` having
+    /// been consumed.
+    fn var_tuple_declaration(&mut self, start: Span) -> Result<Statement, ParseError> {
+        let mut names = vec![self.consume_name("variable name")?];
+        while self.matches(&[TokenType::Comma]) && !self.check(&TokenType::RightBracket) {
+            names.push(self.consume_name("variable name")?);
+        }
+        self.consume(TokenType::RightBracket, "')' after variable names")?;
+        self.consume(TokenType::Equal, "'=' after variable names")?;
+        let initializer = self.whole_expression()?;
+        self.consume(TokenType::Semicolon, "';' after variable declaration")?;
+        Ok(self.new_statement(
+            StatementKind::VarTuple { names, initializer },
+            self.span_from(start),
+        ))
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        self.traced("statement", |parser| {
+            if parser.check(&TokenType::Identifier)
+                && parser.peek_next().token_type == TokenType::Colon
+            {
+                parser.traced("labeledStmt", Self::labeled_statement)
+            } else if parser.matches(&[TokenType::Assert]) {
+                parser.traced("assertStmt", Self::assert_statement)
+            } else if parser.matches(&[TokenType::Break, TokenType::Continue]) {
+                parser.traced("jumpStmt", Self::jump_statement)
+            } else if parser.matches(&[TokenType::For]) {
+                parser.traced("forStmt", Self::for_statement)
+            } else if parser.matches(&[TokenType::If]) {
+                parser.traced("ifStmt", Self::if_statement)
+            } else if parser.matches(&[TokenType::Print]) {
+                parser.traced("printStmt", Self::print_statement)
+            } else if parser.matches(&[TokenType::Return]) {
+                parser.traced("returnStmt", Self::return_statement)
+            } else if parser.matches(&[TokenType::Throw]) {
+                parser.traced("throwStmt", Self::throw_statement)
+            } else if parser.matches(&[TokenType::Try]) {
+                parser.traced("tryStmt", Self::try_statement)
+            } else if parser.matches(&[TokenType::While]) {
+                parser.traced("whileStmt", Self::while_statement)
+            } else if parser.matches(&[TokenType::LeftBrace]) {
+                let start = parser.previous().span;
+                let statements = parser.nested(|parser| parser.traced("block", Self::block))?;
+                Ok(parser.new_statement(StatementKind::Block(statements), parser.span_from(start)))
+            } else if parser.matches(&[TokenType::Semicolon]) {
+                // An empty block does just as little as the empty statement.
+                let semicolon = parser.previous().clone();
+                parser.warnings.push(Warning::EmptyStatement {
+                    semicolon: semicolon.clone(),
+                });
+                Ok(parser.new_statement(StatementKind::Block(Vec::new()), semicolon.span))
+            } else {
+                parser.traced("exprStmt", Self::expression_statement)
+            }
+        })
+    }
+
+    /// Parses `label: loop`. Only loops can be labeled.
+    fn labeled_statement(&mut self) -> Result<Statement, ParseError> {
+        let name = self.advance().clone();
+        self.advance();
+        let mut statement = if self.matches(&[TokenType::For]) {
+            self.traced("forStmt", Self::for_statement)?
+        } else if self.matches(&[TokenType::While]) {
+            self.traced("whileStmt", Self::while_statement)?
+        } else {
+            return Err(ParseError::UnexpectedToken {
+                found: self.peek().clone(),
+                expected: "loop after label",
+            });
+        };
+        statement.span = name.span.to(statement.span);
+        if let StatementKind::For { label, .. } | StatementKind::While { label, .. } =
+            &mut statement.kind
+        {
+            *label = Some(name);
+        }
+        Ok(statement)
+    }
+
+    /// Parses `assert condition;` or `assert condition, message;`.
+    fn assert_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = self.previous().clone();
+        let condition = self.whole_expression()?;
+        let message = if self.matches(&[TokenType::Comma]) {
+            Some(self.whole_expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::Semicolon, "';' after assertion")?;
+        let span = self.span_from(keyword.span);
+        Ok(self.new_statement(
+            StatementKind::Assert {
+                keyword,
+                condition,
+                message,
+            },
+            span,
+        ))
+    }
+
+    /// Parses `break` or `continue`, with an optional label.
+    fn jump_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = self.previous().clone();
+        let label = if self.check(&TokenType::Identifier) {
+            Some(self.advance().clone())
+        } else {
+            None
+        };
+        let expected = match keyword.token_type {
+            TokenType::Break => "';' after 'break'",
+            _ => "';' after 'continue'",
+        };
+        self.consume(TokenType::Semicolon, expected)?;
+        let span = self.span_from(keyword.span);
+        let kind = match keyword.token_type {
+            TokenType::Break => StatementKind::Break { keyword, label },
+            _ => StatementKind::Continue { keyword, label },
+        };
+        Ok(self.new_statement(kind, span))
+    }
+
+    fn for_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LeftBracket, "'(' after 'for'")?;
+        let initializer = if self.matches(&[TokenType::Semicolon]) {
+            None
+        } else if self.matches(&[TokenType::Var]) {
+            Some(self.traced("varDecl", Self::var_declaration)?)
+        } else {
+            Some(self.traced("exprStmt", Self::expression_statement)?)
+        };
+        let condition = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.condition()?)
+        };
+        self.consume(TokenType::Semicolon, "';' after loop condition")?;
+        let increment = if self.check(&TokenType::RightBracket) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightBracket, "')' after for clauses")?;
+        let body = Box::new(self.nested(Self::statement)?);
+        Ok(self.new_statement(
+            StatementKind::For {
+                initializer: initializer.map(Box::new),
+                condition,
+                increment,
+                body,
+                label: None,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LeftBracket, "'(' after 'if'")?;
+        let condition = self.condition()?;
+        self.consume(TokenType::RightBracket, "')' after if condition")?;
+        let then_branch = Box::new(self.nested(Self::statement)?);
+        let else_branch = if self.matches(&[TokenType::Else]) {
+            Some(Box::new(self.nested(Self::statement)?))
+        } else {
+            None
+        };
+        Ok(self.new_statement(
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn print_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        let value = self.whole_expression()?;
+        self.consume(TokenType::Semicolon, "';' after value")?;
+        Ok(self.new_statement(StatementKind::Print(value), self.span_from(start)))
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = self.previous().clone();
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.whole_expression()?)
+        };
+        self.consume(TokenType::Semicolon, "';' after return value")?;
+        let span = self.span_from(keyword.span);
+        Ok(self.new_statement(StatementKind::Return { keyword, value }, span))
+    }
+
+    fn throw_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = self.previous().clone();
+        let value = self.whole_expression()?;
+        self.consume(TokenType::Semicolon, "';' after thrown value")?;
+        let span = self.span_from(keyword.span);
+        Ok(self.new_statement(StatementKind::Throw { keyword, value }, span))
+    }
+
+    /// Parses `try block`, followed by a `catch` clause, a `finally`
+    /// clause, or both.
+    fn try_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        let body = Box::new(self.block_statement("'{' after 'try'")?);
+        let catch = if self.matches(&[TokenType::Catch]) {
+            self.consume(TokenType::LeftBracket, "'(' after 'catch'")?;
+            let name = self.consume_name("exception variable name")?;
+            self.consume(TokenType::RightBracket, "')' after exception variable")?;
+            let body = Box::new(self.block_statement("'{' before catch body")?);
+            Some(Catch { name, body })
+        } else {
+            None
+        };
+        let finally = if self.matches(&[TokenType::Finally]) {
+            Some(Box::new(self.block_statement("'{' after 'finally'")?))
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(ParseError::UnexpectedToken {
+                found: self.peek().clone(),
+                expected: "'catch' or 'finally' after try block",
+            });
+        }
+        Ok(self.new_statement(
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    /// Parses a block that must be there, as a statement of its own.
+    fn block_statement(&mut self, expected: &'static str) -> Result<Statement, ParseError> {
+        let start = self.consume(TokenType::LeftBrace, expected)?.span;
+        let statements = self.nested(|parser| parser.traced("block", Self::block))?;
+        Ok(self.new_statement(StatementKind::Block(statements), self.span_from(start)))
+    }
+
+    fn while_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LeftBracket, "'(' after 'while'")?;
+        let condition = self.condition()?;
+        self.consume(TokenType::RightBracket, "')' after condition")?;
+        let body = Box::new(self.nested(Self::statement)?);
+        Ok(self.new_statement(
+            StatementKind::While {
+                condition,
+                body,
+                increment: None,
+                label: None,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        self.consume(TokenType::RightBrace, "'}' after block")?;
+        Ok(statements)
+    }
+
+    fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let expr = self.whole_expression()?;
+        self.consume(TokenType::Semicolon, "';' after expression")?;
+        Ok(self.new_statement(
+            StatementKind::Expression(expr),
+            self.span_from(self.program[expr].span),
+        ))
+    }
+
+    fn expression(&mut self) -> Result<ExprId, ParseError> {
+        self.nested(|parser| parser.traced("expression", |parser| parser.assignment(false)))
+    }
+
+    /// Parses an expression that is not part of a larger one, so that
+    /// parentheses around all of it are redundant.
+    fn whole_expression(&mut self) -> Result<ExprId, ParseError> {
+        let paren = self.peek().clone();
+        let expr = self.expression()?;
+        self.check_parentheses(paren, expr);
+        Ok(expr)
+    }
+
+    /// Parses the condition of an `if` or loop, which is already in
+    /// parentheses of its own.
+    fn condition(&mut self) -> Result<ExprId, ParseError> {
+        let paren = self.peek().clone();
+        let expr =
+            self.nested(|parser| parser.traced("expression", |parser| parser.assignment(true)))?;
+        self.check_parentheses(paren, expr);
+        Ok(expr)
+    }
+
+    /// Warns if `expr`, which starts at `paren`, is a grouping.
+    fn check_parentheses(&mut self, paren: Token, expr: ExprId) {
+        let expr = &self.program[expr];
+        if matches!(expr.kind, ExprKind::Grouping(_)) && expr.span.start == paren.span.start {
+            self.warnings.push(Warning::RedundantParentheses { paren });
+        }
+    }
+
+    /// Parses an assignment or anything that binds more tightly. In a
+    /// `condition`, an `=` is reported as a likely typo for `==`: as an
+    /// error with nothing assignable on its left, and otherwise as a
+    /// warning.
+    fn assignment(&mut self, condition: bool) -> Result<ExprId, ParseError> {
+        let expr = self.operators(Precedence::Or)?;
+        if !self.matches(&[TokenType::Equal]) {
+            return Ok(expr);
+        }
+        let target = match &self.program[expr].kind {
+            ExprKind::Variable(_) | ExprKind::Get { .. } => true,
+            ExprKind::Tuple(elements) => elements
+                .iter()
+                .all(|element| matches!(self.program[*element].kind, ExprKind::Variable(_))),
+            _ => false,
+        };
+        if !target {
+            // The parser is not confused about where it is, so report the
+            // error without unwinding, parse the right-hand side as usual
+            // and keep the left operand in place of the assignment.
+            let equals = self.previous().clone();
+            self.errors.push(if condition {
+                ParseError::EqualsInCondition { equals }
+            } else {
+                ParseError::InvalidAssignmentTarget { equals }
+            });
+            self.expression()?;
+            return Ok(expr);
+        }
+        if condition {
+            let equals = self.previous().clone();
+            self.warnings
+                .push(Warning::AssignmentInCondition { equals });
+        }
+        let value = self.expression()?;
+        let span = self.program[expr].span.to(self.program[value].span);
+        let kind = match &self.program[expr].kind {
+            ExprKind::Variable(name) => ExprKind::Assign {
+                name: name.clone(),
+                value,
+            },
+            ExprKind::Get { object, name } => ExprKind::Set {
+                object: *object,
+                name: name.clone(),
+                value,
+            },
+            ExprKind::Tuple(elements) => ExprKind::AssignTuple {
+                targets: elements.clone(),
+                value,
+            },
+            _ => unreachable!("assignment targets were checked above"),
+        };
+        Ok(self.add_expr(kind, span))
+    }
+
+    fn operators(&mut self, min: Precedence) -> Result<ExprId, ParseError> {
+        self.traced(min.rule(), |parser| parser.operator_expression(min))
+    }
+
+    /// Parses an operator expression in which every infix operator binds
+    /// at least as tightly as `min`. Operators and their precedences come
+    /// from [`prefix_operator`] and [`infix_operator`].
+    fn operator_expression(&mut self, min: Precedence) -> Result<ExprId, ParseError> {
+        let expr = match prefix_operator(&self.peek().token_type) {
+            Some(operator) => {
+                let start = self.advance().span;
+                let right = self.nested(|parser| parser.operators(Precedence::Unary))?;
+                let span = start.to(self.program[right].span);
+                self.add_expr(ExprKind::Unary { operator, right }, span)
+            }
+            None => self.traced("call", Self::call)?,
+        };
+        self.chained(|parser| parser.infix_operators(expr, min))
+    }
+
+    /// Parses the infix operators that follow `expr` and bind at least as
+    /// tightly as `min`. Each one puts the expression so far a level deeper
+    /// in the tree, as its left operand.
+    fn infix_operators(&mut self, mut expr: ExprId, min: Precedence) -> Result<ExprId, ParseError> {
+        while let Some((operator, precedence)) = infix_operator(&self.peek().token_type) {
+            if precedence < min {
+                break;
+            }
+            self.deeper()?;
+            self.advance();
+            // Binding the right operand one level tighter makes every
+            // infix operator left-associative.
+            let right = self.operators(precedence.next())?;
+            let span = self.program[expr].span.to(self.program[right].span);
+            let left = expr;
+            let kind = match operator {
+                InfixOp::Binary(operator) => ExprKind::Binary {
+                    left,
+                    operator,
+                    right,
+                },
+                InfixOp::Logical(operator) => ExprKind::Logical {
+                    left,
+                    operator,
+                    right,
+                },
+            };
+            expr = self.add_expr(kind, span);
+        }
+        Ok(expr)
+    }
+
+    fn call(&mut self) -> Result<ExprId, ParseError> {
+        let expr = self.traced("primary", Self::primary)?;
+        self.chained(|parser| parser.calls(expr))
+    }
+
+    /// Parses the calls and property accesses that follow `expr`. Like
+    /// infix operators, each puts the expression so far a level deeper.
+    fn calls(&mut self, mut expr: ExprId) -> Result<ExprId, ParseError> {
+        loop {
+            if matches!(
+                self.peek().token_type,
+                TokenType::LeftBracket | TokenType::Dot
+            ) {
+                self.deeper()?;
+            }
+            if self.matches(&[TokenType::LeftBracket]) {
+                expr = self.finish_call(expr)?;
+            } else if self.matches(&[TokenType::Dot]) {
+                let name = self.consume_name("property name after '.'")?;
+                let span = self.program[expr].span.to(name.token.span);
+                expr = self.add_expr(ExprKind::Get { object: expr, name }, span);
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: ExprId) -> Result<ExprId, ParseError> {
+        let mut arguments = Vec::new();
+        while !self.check(&TokenType::RightBracket) {
+            if arguments.len() == MAX_ARITY {
+                let found = self.peek().clone();
+                self.errors.push(ParseError::TooManyArguments { found });
+            }
+            arguments.push(self.expression()?);
+            if !self.matches(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        let paren = self.consume(TokenType::RightBracket, "')' after arguments")?;
+        let span = self.program[callee].span.to(paren.span);
+        Ok(self.add_expr(
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            },
+            span,
+        ))
+    }
+
+    fn primary(&mut self) -> Result<ExprId, ParseError> {
+        let token = self.peek().clone();
+        let kind = match token.token_type {
+            TokenType::False
+            | TokenType::True
+            | TokenType::Nil
+            | TokenType::Number(_)
+            | TokenType::String(_)
+            | TokenType::Char(_) => {
+                self.advance();
+                ExprKind::Literal(Literal::new(token.clone()))
+            }
+            TokenType::Super => {
+                self.advance();
+                self.consume(TokenType::Dot, "'.' after 'super'")?;
+                let method = self.consume_name("superclass method name")?;
+                ExprKind::Super {
+                    keyword: Name::new(token.clone()),
+                    method,
+                }
+            }
+            TokenType::This => {
+                self.advance();
+                ExprKind::This(Name::new(token.clone()))
+            }
+            TokenType::Identifier => {
+                self.advance();
+                ExprKind::Variable(Name::new(token.clone()))
+            }
+            TokenType::LeftBracket => {
+                self.advance();
+                let expr = self.expression()?;
+                if self.matches(&[TokenType::Comma]) {
+                    let elements = self.tuple(expr)?;
+                    let span = self.span_from(token.span);
+                    return Ok(self.add_expr(ExprKind::Tuple(elements), span));
+                }
+                self.consume(TokenType::RightBracket, "')' after expression")?;
+                if matches!(
+                    self.program[expr].kind,
+                    ExprKind::Grouping(_)
+                        | ExprKind::Literal(_)
+                        | ExprKind::Super { .. }
+                        | ExprKind::This(_)
+                        | ExprKind::Variable(_)
+                ) {
+                    self.warnings.push(Warning::RedundantParentheses {
+                        paren: token.clone(),
+                    });
+                }
+                ExprKind::Grouping(expr)
+            }
+            _ => return Err(ParseError::ExpectedExpression { found: token }),
+        };
+        let span = self.span_from(token.span);
+        Ok(self.add_expr(kind, span))
+    }
+
+    /// Runs `parse` as the grammar rule `rule`, recording it in the trace
+    /// if there is one.
+    fn traced<T>(
+        &mut self,
+        rule: &'static str,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let Some(trace) = &mut self.trace else {
+            return parse(self);
+        };
+        let index = trace.len();
+        trace.push(TraceStep {
+            rule,
+            depth: self.trace_depth,
+            tokens: self.current..self.current,
+            failed: false,
+        });
+        self.trace_depth += 1;
+        let result = parse(self);
+        self.trace_depth -= 1;
+        if let Some(trace) = &mut self.trace {
+            trace[index].tokens.end = self.current;
+            trace[index].failed = result.is_err();
+        }
+        result
+    }
+
+    /// Runs `parse` one nesting level deeper, failing instead if that would
+    /// exceed [`MAX_DEPTH`].
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.chained(|parser| {
+            parser.deeper()?;
+            // Even within MAX_DEPTH, a thread with a small stack, such as
+            // a test's, can run out of it.
+            stack::grow(|| parse(parser))
+        })
+    }
+
+    /// Runs `parse`, which may go [`deeper`](Self::deeper) any number of
+    /// times, and returns to the current depth afterwards.
+    fn chained<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Goes one nesting level deeper, until the end of the enclosing
+    /// [`chained`](Self::chained), failing instead if that would exceed
+    /// [`MAX_DEPTH`].
+    fn deeper(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError::TooDeep {
+                found: self.peek().clone(),
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn new_statement(&mut self, kind: StatementKind, span: Span) -> Statement {
+        Statement {
+            id: self.program.next_statement_id(),
+            kind,
+            span,
+        }
+    }
+
+    fn add_expr(&mut self, kind: ExprKind, span: Span) -> ExprId {
+        self.program.add_expr(Expr { kind, span })
+    }
+
+    /// Consumes an identifier, as `consume` does, and interns it.
+    fn consume_name(&mut self, expected: &'static str) -> Result<Name, ParseError> {
+        self.consume(TokenType::Identifier, expected).map(Name::new)
+    }
+
+    fn matches(&mut self, token_types: &[TokenType]) -> bool {
+        for token_type in token_types {
+            if self.check(token_type) {
+                self.advance();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn consume(
+        &mut self,
+        token_type: TokenType,
+        expected: &'static str,
+    ) -> Result<Token, ParseError> {
+        if self.check(&token_type) {
+            return Ok(self.advance().clone());
+        }
+        Err(ParseError::UnexpectedToken {
+            found: self.peek().clone(),
+            expected,
+        })
+    }
+
+    /// Checks the type of the current token. Literal types compare by
+    /// kind only, ignoring their values.
+    fn check(&self, token_type: &TokenType) -> bool {
+        !self.is_at_end()
+            && std::mem::discriminant(&self.peek().token_type) == std::mem::discriminant(token_type)
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::Eof
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    /// The token after the current one, or the current one at the end.
+    fn peek_next(&self) -> &Token {
+        self.tokens.get(self.current + 1).unwrap_or(self.peek())
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    /// Returns the span from `start` to the end of the previous token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
+    /// Discards tokens until the start of the next statement.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+            match self.peek().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::Assert
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::Break
+                | TokenType::Continue => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+}
+
+/// How tightly operators bind, from loosest to tightest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+}
+
+impl Precedence {
+    /// The name of the grammar rule for operators of this precedence, as
+    /// Lox's grammar is usually written: with one rule per level.
+    fn rule(self) -> &'static str {
+        match self {
+            Self::Or => "logic_or",
+            Self::And => "logic_and",
+            Self::Equality => "equality",
+            Self::Comparison => "comparison",
+            Self::Term => "term",
+            Self::Factor => "factor",
+            Self::Unary => "unary",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Self::Or => Self::And,
+            Self::And => Self::Equality,
+            Self::Equality => Self::Comparison,
+            Self::Comparison => Self::Term,
+            Self::Term => Self::Factor,
+            Self::Factor | Self::Unary => Self::Unary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum InfixOp {
+    Binary(BinaryOp),
+    Logical(LogicalOp),
+}
+
+fn prefix_operator(token_type: &TokenType) -> Option<UnaryOp> {
+    match token_type {
+        TokenType::Bang => Some(UnaryOp::Not),
+        TokenType::Minus => Some(UnaryOp::Negate),
+        _ => None,
+    }
+}
+
+fn infix_operator(token_type: &TokenType) -> Option<(InfixOp, Precedence)> {
+    use InfixOp::{Binary, Logical};
+    let operator = match token_type {
+        TokenType::Or => (Logical(LogicalOp::Or), Precedence::Or),
+        TokenType::And => (Logical(LogicalOp::And), Precedence::And),
+        TokenType::BangEqual => (Binary(BinaryOp::NotEqual), Precedence::Equality),
+        TokenType::EqualEqual => (Binary(BinaryOp::Equal), Precedence::Equality),
+        TokenType::Greater => (Binary(BinaryOp::Greater), Precedence::Comparison),
+        TokenType::GreaterEqual => (Binary(BinaryOp::GreaterEqual), Precedence::Comparison),
+        TokenType::Less => (Binary(BinaryOp::Less), Precedence::Comparison),
+        TokenType::LessEqual => (Binary(BinaryOp::LessEqual), Precedence::Comparison),
+        TokenType::Minus => (Binary(BinaryOp::Subtract), Precedence::Term),
+        TokenType::Plus => (Binary(BinaryOp::Add), Precedence::Term),
+        TokenType::Slash => (Binary(BinaryOp::Divide), Precedence::Factor),
+        TokenType::Star => (Binary(BinaryOp::Multiply), Precedence::Factor),
+        TokenType::Percent => (Binary(BinaryOp::Remainder), Precedence::Factor),
+        _ => return None,
+    };
+    Some(operator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{scan_tokens, scan_tokens_with_options, ScannerOptions};
+    use crate::unstable::ast_printer::print_statement;
+
+    fn parse_source(source: &str) -> Result<Program, ParseErrors> {
+        parse(scan_tokens(source.as_bytes()).unwrap())
+    }
+
+    /// The s-expression for each statement in `source`.
+    fn sexprs(source: &str) -> Vec<String> {
+        let program = parse_source(source).unwrap();
+        program
+            .statements
+            .iter()
+            .map(|statement| print_statement(&program, statement))
+            .collect()
+    }
+
+    /// The warnings parsing `source` reports, as the CLI prints them.
+    fn warnings(source: &str) -> Vec<String> {
+        let tokens = scan_tokens(source.as_bytes()).unwrap();
+        let (_, warnings) = parse_with_warnings(tokens).unwrap();
+        warnings.iter().map(ToString::to_string).collect()
+    }
+
+    /// The errors parsing `source` reports, as the CLI prints them.
+    fn errors(source: &str) -> Vec<String> {
+        let errors = parse_source(source).unwrap_err().errors;
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn binds_operators_by_precedence() {
+        assert_eq!(
+            sexprs("print 1 + 2 * 3 - 4 / -5 == 6 or !a and b;"),
+            ["(print (or (== (- (+ 1 (* 2 3)) (/ 4 (- 5))) 6) (and (! a) b)))"]
+        );
+        assert_eq!(
+            sexprs("print 1 < 2 == 3 >= 4;"),
+            ["(print (== (< 1 2) (>= 3 4)))"]
+        );
+    }
+
+    #[test]
+    fn associates_binary_operators_left_and_assignment_right() {
+        assert_eq!(
+            sexprs("1 - 2 - 3; a = b = c; a or b or c;"),
+            [
+                "(; (- (- 1 2) 3))",
+                "(; (= a (= b c)))",
+                "(; (or (or a b) c))",
+            ]
+        );
+    }
+
+    #[test]
+    fn binds_calls_and_property_access_tightest() {
+        assert_eq!(
+            sexprs("-a.b(1)(2); a.b.c(1)(2);"),
+            [
+                "(; (- (call (call (. a b) 1) 2)))",
+                "(; (call (call (. (. a b) c) 1) 2))"
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_one_trailing_comma_in_arguments_and_parameters() {
+        assert_eq!(
+            sexprs("fun g(x, y,) {} g(1, 2,);"),
+            ["(fun g (x y))", "(; (call g 1 2))"]
+        );
+        assert_eq!(
+            errors("g(1,,);\nfun h(,) {}"),
+            [
+                "[line 1:5] Error at ',': Expected expression.",
+                "[line 2:7] Error at ',': Expected parameter name.",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_empty_statements() {
+        assert_eq!(
+            warnings("print 1;;\n;\nfor (;;) {}"),
+            [
+                "[line 1:9] Warning at ';': Empty statement.",
+                "[line 2:1] Warning at ';': Empty statement.",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_parentheses_that_change_nothing() {
+        assert_eq!(
+            warnings("print (1);\nprint ((1 + 2)) * 3;\nif ((x)) {}\nprint (1 + 2) * 3;"),
+            [
+                "[line 1:7] Warning at '(': Redundant parentheses.",
+                "[line 2:7] Warning at '(': Redundant parentheses.",
+                "[line 3:5] Warning at '(': Redundant parentheses.",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_an_assignment_as_a_whole_condition() {
+        assert_eq!(
+            warnings("if (x = 3) {}\nwhile (a.b = c) {}\nfor (; x = 1;) {}"),
+            [
+                "[line 1:7] Warning at '=': Assignment used as a condition; did you mean '=='?",
+                "[line 2:12] Warning at '=': Assignment used as a condition; did you mean '=='?",
+                "[line 3:10] Warning at '=': Assignment used as a condition; did you mean '=='?",
+            ]
+        );
+        assert!(warnings("if (x == 3) {}\nif (f(x = 3)) {}\nx = 3;").is_empty());
+        assert_eq!(
+            errors("if (1 = 3) {}"),
+            ["[line 1:7] Error at '=': Invalid assignment target; did you mean '=='?"]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_nesting_limit() {
+        let too_deep = |source: String| {
+            let errors = parse_source(&source).unwrap_err().errors;
+            matches!(&errors[..], [ParseError::TooDeep { .. }])
+        };
+        let parens = format!("print {}1{};", "(".repeat(10_000), ")".repeat(10_000));
+        assert!(too_deep(parens));
+        assert!(too_deep(format!("print {}1;", "-".repeat(10_000))));
+        assert!(too_deep(format!("print 1{};", " + 1".repeat(20_000))));
+        assert!(too_deep(format!("a{};", ".b".repeat(200_000))));
+        assert!(too_deep(format!("f{};", "()".repeat(10_000))));
+        assert!(too_deep("{".repeat(10_000)));
+    }
+
+    #[test]
+    fn allows_nesting_up_to_the_limit() {
+        let depth = MAX_DEPTH - 2;
+        assert!(parse_source(&format!("print 1{};", " + 1".repeat(depth))).is_ok());
+        assert!(parse_source(&format!("a{};", ".b".repeat(depth))).is_ok());
+        let parens = format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_source(&parens).is_ok());
+    }
+
+    #[test]
+    fn reports_an_error_in_each_statement() {
+        assert_eq!(
+            errors("print (1;\nvar = 2;\nprint 3;\nf(,);"),
+            [
+                "[line 1:9] Error at ';': Expected ')' after expression.",
+                "[line 2:5] Error at '=': Expected variable name.",
+                "[line 4:3] Error at ',': Expected expression.",
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_at_the_next_statement_keyword() {
+        // No `;` ends the bad statement, so parsing picks up at `var`.
+        assert_eq!(
+            errors("print 1 + ) var x = 1; class { }"),
+            [
+                "[line 1:11] Error at ')': Expected expression.",
+                "[line 1:30] Error at '{': Expected class name.",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_offending_token_and_what_was_expected() {
+        let errors = parse_source("print (1 2);").unwrap_err().errors;
+        let [ParseError::UnexpectedToken { found, expected }] = &errors[..] else {
+            panic!("unexpected errors: {errors:?}");
+        };
+        assert_eq!(found.token_type, TokenType::Number(2.0));
+        assert_eq!((found.span.line, found.span.column), (1, 10));
+        assert_eq!(*expected, "')' after expression");
+    }
+
+    #[test]
+    fn errors_at_the_end_say_so() {
+        assert_eq!(
+            errors("print 1"),
+            ["[line 1:8] Error at end: Expected ';' after value."]
+        );
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        assert_eq!(
+            errors("fun f() {\n  print ;\n}\nfun g() {\n  var 1;\n}"),
+            [
+                "[line 2:9] Error at ';': Expected expression.",
+                "[line 5:7] Error at '1': Expected variable name.",
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_name_an_enclosing_loop() {
+        assert!(
+            parse_source("outer: while (true) { for (;;) { break outer; continue outer; } }")
+                .is_ok()
+        );
+        assert_eq!(
+            errors(
+                "a: while (true) { break b; }\nb: while (true) { while (true) { continue a; } }"
+            ),
+            [
+                "[line 1:25] Error at 'b': No enclosing loop has this label.",
+                "[line 2:43] Error at 'a': No enclosing loop has this label.",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_loops_take_a_label() {
+        assert_eq!(
+            errors("a: a: while (true) {}"),
+            ["[line 1:4] Error at 'a': Expected loop after label."]
+        );
+        assert_eq!(
+            errors("a: print 1;"),
+            ["[line 1:4] Error at 'print': Expected loop after label."]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_need_a_loop() {
+        assert_eq!(
+            errors("break;\n{ continue; }\nwhile (true) { fun f() { break; } }"),
+            [
+                "[line 1:1] Error at 'break': Can't use 'break' outside of a loop.",
+                "[line 2:3] Error at 'continue': Can't use 'continue' outside of a loop.",
+                "[line 3:26] Error at 'break': Can't use 'break' outside of a loop.",
+            ]
+        );
+    }
+
+    #[test]
+    fn declarations_keep_their_documentation() {
+        let program = parse_source(
+            "/// A class.\nclass A {\n  /// A method.\n  m() {}\n  n() {}\n}\n\
+             /// A function.\nfun f() {}\n\
+             /// Dropped.\n\nfun g() {}",
+        )
+        .unwrap();
+        let StatementKind::Class { doc, methods, .. } = &program.statements[0].kind else {
+            unreachable!()
+        };
+        assert_eq!(doc.as_deref(), Some("A class."));
+        assert_eq!(methods[0].doc.as_deref(), Some("A method."));
+        assert_eq!(methods[1].doc, None);
+        let docs: Vec<_> = program.statements[1..]
+            .iter()
+            .map(|statement| match &statement.kind {
+                StatementKind::Function(function) => function.doc.as_deref(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(docs, [Some("A function."), None]);
+    }
+
+    /// Each comment in `source`, with the s-expression of the statement it
+    /// is attached to and where it sits relative to that statement.
+    fn comments(source: &str) -> Vec<(String, Option<String>, CommentPlacement)> {
+        let options = ScannerOptions {
+            keep_comments: true,
+            ..ScannerOptions::default()
+        };
+        let tokens = scan_tokens_with_options(source.as_bytes(), &options).unwrap();
+        let program = parse_lossless(tokens).unwrap();
+        let statements = preorder(&program.statements);
+        program
+            .comments
+            .iter()
+            .map(|comment| {
+                let statement = comment.statement.map(|id| {
+                    let statement = statements.iter().find(|statement| statement.id == id);
+                    print_statement(&program, statement.unwrap())
+                });
+                (comment.text.clone(), statement, comment.placement)
+            })
+            .collect()
+    }
+
+    fn comment(
+        text: &str,
+        statement: Option<&str>,
+        placement: CommentPlacement,
+    ) -> (String, Option<String>, CommentPlacement) {
+        (text.to_owned(), statement.map(str::to_owned), placement)
+    }
+
+    #[test]
+    fn comments_on_their_own_line_lead_the_next_statement() {
+        assert_eq!(
+            comments("print 1;\n// two\n  // and more\nprint 2;"),
+            [
+                comment(" two", Some("(print 2)"), CommentPlacement::Leading),
+                comment(" and more", Some("(print 2)"), CommentPlacement::Leading),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_after_code_on_the_same_line_trail_it() {
+        assert_eq!(
+            comments("print 1; // one\nprint 2;"),
+            [comment(
+                " one",
+                Some("(print 1)"),
+                CommentPlacement::Trailing
+            )]
+        );
+    }
+
+    #[test]
+    fn comments_inside_a_block_stay_in_it() {
+        assert_eq!(
+            comments(
+                "{\n  // first\n  print 1;\n  print 2; // second\n  // last\n}\n{\n  // alone\n}"
+            ),
+            [
+                comment(" first", Some("(print 1)"), CommentPlacement::Leading),
+                comment(" second", Some("(print 2)"), CommentPlacement::Trailing),
+                comment(" last", Some("(print 2)"), CommentPlacement::End),
+                comment(" alone", Some("(block)"), CommentPlacement::End),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_after_the_last_statement_end_the_program() {
+        assert_eq!(
+            comments("print 1;\nprint 2;\n// done"),
+            [comment(" done", Some("(print 2)"), CommentPlacement::End)]
+        );
+        assert_eq!(
+            comments("// nothing here"),
+            [comment(" nothing here", None, CommentPlacement::End)]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Works out, before the program runs, which declaration each variable
+//! reference refers to. The interpreter then reads a local variable from
+//! its numbered slot in the one scope that holds it, rather than searching
+//! every scope between the reference and the globals by name.
+
+use crate::{
+    ast::{Binding, ExprId, ExprKind, Function, Name, Program, Statement, StatementKind},
+    intern::Symbol,
+    parser::Warning,
+    unstable::stack,
+};
+
+/// Resolves every variable reference in `program`, which must already be
+/// [lowered](crate::unstable::lower::lower): lowering adds scopes. Changing
+/// the program afterwards may leave the resolution stale, so resolve it
+/// again then.
+pub fn resolve(program: &mut Program) {
+    resolve_with_warnings(program);
+}
+
+/// Like [`resolve`], but also returns [`Warning`]s about local variables
+/// that shadow or redeclare others, in source order.
+pub fn resolve_with_warnings(program: &mut Program) -> Vec<Warning> {
+    let mut resolver = Resolver {
+        program,
+        scopes: Vec::new(),
+        locals: Vec::new(),
+        warnings: Vec::new(),
+    };
+    for statement in &program.statements {
+        resolver.statement(statement);
+    }
+    if let Some(result) = program.result {
+        resolver.expr(result);
+    }
+    let Resolver {
+        locals,
+        mut warnings,
+        ..
+    } = resolver;
+    program.set_locals(locals);
+    warnings.sort_by_key(|warning| warning.token().span.start);
+    warnings
+}
+
+struct Resolver<'a> {
+    program: &'a Program,
+    /// The names declared in each scope the interpreter will create around
+    /// the statement being resolved, innermost last, each scope's in the
+    /// order the interpreter will define them. Top-level code has none:
+    /// its variables are globals.
+    scopes: Vec<Vec<Symbol>>,
+    locals: Vec<(ExprId, Binding)>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Resolver<'a> {
+    fn statement(&mut self, statement: &'a Statement) {
+        // Trees built by hand can nest deeper than the parser allows.
+        stack::grow(|| self.resolve_statement(statement));
+    }
+
+    fn resolve_statement(&mut self, statement: &'a Statement) {
+        match &statement.kind {
+            StatementKind::Block(statements) => {
+                self.scopes.push(Vec::new());
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.scopes.pop();
+            }
+            StatementKind::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+                ..
+            } => {
+                self.declare(name);
+                if let Some(superclass) = superclass {
+                    self.expr(*superclass);
+                    self.scopes.push(vec![Symbol::permanent("super")]);
+                }
+                self.scopes.push(vec![Symbol::permanent("this")]);
+                for field in fields {
+                    self.expr(field.initializer);
+                }
+                for method in methods {
+                    self.function(method);
+                }
+                self.scopes.pop();
+                if superclass.is_some() {
+                    self.scopes.pop();
+                }
+            }
+            StatementKind::Function(function) => {
+                // Declared first, so that the function can call itself.
+                self.declare(&function.name);
+                self.function(function);
+            }
+            // The exception variable lives in a scope of its own, around
+            // the block of the `catch` clause.
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.statement(body);
+                if let Some(catch) = catch {
+                    self.scopes.push(Vec::new());
+                    self.declare(&catch.name);
+                    self.statement(&catch.body);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+            }
+            // The initializer is resolved before the variable exists, so a
+            // variable named in its own initializer is the one it shadows.
+            StatementKind::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.expr(*initializer);
+                }
+                self.declare(name);
+            }
+            StatementKind::VarTuple { names, initializer } => {
+                self.expr(*initializer);
+                for name in names {
+                    self.declare(name);
+                }
+            }
+            _ => {
+                for expr in statement.exprs() {
+                    self.expr(expr);
+                }
+                for child in statement.children() {
+                    self.statement(child);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, function: &'a Function) {
+        self.scopes.push(Vec::new());
+        for param in &function.params {
+            self.declare(param);
+        }
+        for statement in &function.body {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, expr: ExprId) {
+        stack::grow(|| self.resolve_expr(expr));
+    }
+
+    fn resolve_expr(&mut self, expr: ExprId) {
+        let kind = &self.program[expr].kind;
+        match kind {
+            ExprKind::Assign { name, .. }
+            | ExprKind::Super { keyword: name, .. }
+            | ExprKind::This(name)
+            | ExprKind::Variable(name) => self.local(expr, &name.symbol),
+            _ => {}
+        }
+        for child in kind.children() {
+            self.expr(child);
+        }
+    }
+
+    /// Adds `name` to the innermost scope, warning if it hides or
+    /// replaces another local variable. Globals may be redeclared freely.
+    /// A variable declared again keeps its slot.
+    fn declare(&mut self, name: &'a Name) {
+        let Some((scope, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        let token = name.token.clone();
+        if scope.contains(&name.symbol) {
+            self.warnings
+                .push(Warning::RedeclaredVariable { name: token });
+            return;
+        }
+        if enclosing.iter().any(|scope| scope.contains(&name.symbol)) {
+            self.warnings
+                .push(Warning::ShadowedVariable { name: token });
+        }
+        scope.push(name.symbol.clone());
+    }
+
+    /// Records how far out `name`, referred to by `expr`, was declared,
+    /// and in which slot of its scope. A name in no scope is left to be
+    /// found among the globals.
+    fn local(&mut self, expr: ExprId, name: &Symbol) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                let slot = scope.iter().position(|declared| declared == name)?;
+                Some(Binding::Local { depth, slot })
+            });
+        if let Some(binding) = found {
+            self.locals.push((expr, binding));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse, scanner::scan_tokens, unstable::lower::lower};
+
+    /// The warnings resolving `source` reports, as the CLI prints them.
+    fn warnings(source: &str) -> Vec<String> {
+        let mut program = parse(scan_tokens(source.as_bytes()).unwrap()).unwrap();
+        lower(&mut program);
+        let warnings = resolve_with_warnings(&mut program);
+        warnings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn warns_about_shadowed_locals() {
+        assert_eq!(
+            warnings("{ var a = 1; { var a = 2; } }"),
+            ["[line 1:20] Warning at 'a': Shadows a variable of the same name in an enclosing scope."]
+        );
+    }
+
+    #[test]
+    fn warns_about_redeclared_locals() {
+        assert_eq!(
+            warnings("{ var a = 1;\nvar a = 2; }"),
+            ["[line 2:5] Warning at 'a': Already a variable with this name in this scope."]
+        );
+    }
+
+    #[test]
+    fn parameters_share_the_scope_of_the_body() {
+        assert_eq!(
+            warnings("fun f(a, b) {\nvar b; { var a; } }"),
+            [
+                "[line 2:5] Warning at 'b': Already a variable with this name in this scope.",
+                "[line 2:14] Warning at 'a': Shadows a variable of the same name in an enclosing scope.",
+            ]
+        );
+        assert_eq!(
+            warnings("fun f(a, a) {}"),
+            ["[line 1:10] Warning at 'a': Already a variable with this name in this scope."]
+        );
+    }
+
+    #[test]
+    fn the_catch_variable_has_a_scope_of_its_own() {
+        assert!(warnings("try {} catch (e) {}").is_empty());
+        assert_eq!(
+            warnings("{ var e; try {} catch (e) { var e; } }"),
+            [
+                "[line 1:24] Warning at 'e': Shadows a variable of the same name in an enclosing scope.",
+                "[line 1:33] Warning at 'e': Shadows a variable of the same name in an enclosing scope.",
+            ]
+        );
+    }
+
+    #[test]
+    fn globals_do_not_warn() {
+        assert!(warnings("var a = 1; var a = 2; fun f() {} var f;").is_empty());
+        // Only locals count as shadowed, and a global is not one.
+        assert!(warnings("var a; { var a; } fun g(a) {}").is_empty());
+    }
+}
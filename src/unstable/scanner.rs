@@ -0,0 +1,1103 @@
+use std::{error, fmt, ops::Range, slice, str, vec};
+
+use phf::phf_map;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum TokenType {
+    // Single-character tokens.
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Colon,
+    Comma,
+    Dot,
+    /// `...`, in front of a function's rest parameter.
+    Ellipsis,
+    Minus,
+    Plus,
+    Semicolon,
+    Slash,
+    Star,
+    Percent,
+
+    // One or two character tokens.
+    Bang,
+    BangEqual,
+    Equal,
+    EqualEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+
+    // Literals.
+    Identifier,
+    String(String),
+    Number(f64),
+    Char(char),
+
+    // Keywords.
+    And,
+    Assert,
+    Break,
+    Catch,
+    Class,
+    Continue,
+    Else,
+    False,
+    Finally,
+    Fun,
+    For,
+    If,
+    Nil,
+    Or,
+    Print,
+    Return,
+    Super,
+    This,
+    Throw,
+    True,
+    Try,
+    Var,
+    While,
+
+    /// An ordinary `//` comment, slashes included in the lexeme. Only
+    /// produced when [`ScannerOptions::keep_comments`] is set.
+    Comment,
+
+    Eof,
+}
+
+// The word operators `is`, `mod` and `not` scan to the same token types
+// as `==`, `%` and `!`, so the parser needs no special handling for them.
+static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
+    "and" => TokenType::And,
+    "assert" => TokenType::Assert,
+    "break" => TokenType::Break,
+    "catch" => TokenType::Catch,
+    "class" => TokenType::Class,
+    "continue" => TokenType::Continue,
+    "else" => TokenType::Else,
+    "false" => TokenType::False,
+    "finally" => TokenType::Finally,
+    "for" => TokenType::For,
+    "fun" => TokenType::Fun,
+    "if" => TokenType::If,
+    "is" => TokenType::EqualEqual,
+    "mod" => TokenType::Percent,
+    "nil" => TokenType::Nil,
+    "not" => TokenType::Bang,
+    "or" => TokenType::Or,
+    "print" => TokenType::Print,
+    "return" => TokenType::Return,
+    "super" => TokenType::Super,
+    "this" => TokenType::This,
+    "throw" => TokenType::Throw,
+    "true" => TokenType::True,
+    "try" => TokenType::Try,
+    "var" => TokenType::Var,
+    "while" => TokenType::While,
+};
+
+/// A range of bytes in the source, along with the position it starts at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Span {
+    /// Byte offset of the first byte.
+    pub start: usize,
+    /// Byte offset one past the last byte.
+    pub end: usize,
+    pub line: usize,
+    /// One-based byte offset of the first character within its line.
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns the span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub span: Span,
+    /// Text of the `///` comments directly preceding this token, with no
+    /// blank line between, one line per comment with the leading `///`
+    /// and a single space removed.
+    pub doc: Option<String>,
+}
+
+/// A run of source bytes classified by [`scan_lossless`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Piece {
+    pub kind: PieceKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PieceKind {
+    Token(TokenType),
+    /// Spaces, tabs, line breaks and any byte order mark. Consecutive
+    /// whitespace forms a single piece.
+    Whitespace,
+    /// An ordinary `//` comment, up to the end of its line.
+    Comment,
+    /// A `///` doc comment, up to the end of its line.
+    DocComment,
+    /// Bytes that failed to scan, such as an unterminated string.
+    Error(ScanError),
+}
+
+/// Resource bounds for scanning untrusted input. `None` means unbounded.
+#[derive(Debug, Clone, Default)]
+pub struct ScannerOptions {
+    /// Maximum number of tokens produced, not counting the final `Eof`.
+    pub max_tokens: Option<usize>,
+    /// Maximum length in bytes of the contents of a string literal.
+    pub max_string_length: Option<usize>,
+    /// Maximum length in bytes of the whole source.
+    pub max_source_size: Option<usize>,
+    /// Emit ordinary comments as [`TokenType::Comment`] tokens instead of
+    /// discarding them. Doc comments are attached to tokens either way.
+    pub keep_comments: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Tokens,
+    StringLength,
+    SourceSize,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tokens => write!(f, "token count"),
+            Self::StringLength => write!(f, "string length"),
+            Self::SourceSize => write!(f, "source size"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanError {
+    UnexpectedCharacter {
+        line: usize,
+        character: u8,
+    },
+    UnterminatedString {
+        line: usize,
+    },
+    UnterminatedChar {
+        line: usize,
+    },
+    InvalidChar {
+        line: usize,
+    },
+    InvalidEscape {
+        line: usize,
+    },
+    InvalidUtf8 {
+        line: usize,
+    },
+    InvalidNumber {
+        line: usize,
+    },
+    LimitExceeded {
+        line: usize,
+        limit: Limit,
+        max: usize,
+    },
+}
+
+impl ScanError {
+    /// The line on which the error was detected.
+    pub fn line(&self) -> usize {
+        match self {
+            Self::UnexpectedCharacter { line, .. }
+            | Self::UnterminatedString { line }
+            | Self::UnterminatedChar { line }
+            | Self::InvalidChar { line }
+            | Self::InvalidEscape { line }
+            | Self::InvalidUtf8 { line }
+            | Self::InvalidNumber { line }
+            | Self::LimitExceeded { line, .. } => *line,
+        }
+    }
+
+    /// Describes the error without saying where it is.
+    fn message(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedCharacter { character, .. } => {
+                write!(f, "Unexpected character '{}'.", character.escape_ascii())
+            }
+            Self::UnterminatedString { .. } => write!(f, "Unterminated string."),
+            Self::UnterminatedChar { .. } => write!(f, "Unterminated character literal."),
+            Self::InvalidChar { .. } => {
+                write!(f, "Character literal must contain exactly one character.")
+            }
+            Self::InvalidEscape { .. } => write!(f, "Invalid escape sequence."),
+            Self::InvalidUtf8 { .. } => write!(f, "Literal is not valid UTF-8."),
+            Self::InvalidNumber { .. } => write!(f, "Invalid number literal."),
+            Self::LimitExceeded { limit, max, .. } => {
+                write!(f, "Maximum {limit} of {max} exceeded.")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: ", self.line())?;
+        self.message(f)
+    }
+}
+
+impl error::Error for ScanError {}
+
+#[derive(Debug)]
+pub struct ScanErrors {
+    errors: Vec<ScanError>,
+}
+
+impl ScanErrors {
+    /// The individual errors, in the order they were found.
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+}
+
+impl<'a> IntoIterator for &'a ScanErrors {
+    type Item = &'a ScanError;
+    type IntoIter = slice::Iter<'a, ScanError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
+
+impl IntoIterator for ScanErrors {
+    type Item = ScanError;
+    type IntoIter = vec::IntoIter<ScanError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+/// Lists the errors by line. Several errors on one line share a heading:
+///
+/// ```text
+/// [line 1] Error: Unterminated string.
+/// [line 3] Errors:
+///   Unexpected character '@'.
+///   Unexpected character '#'.
+/// ```
+impl fmt::Display for ScanErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut errors: Vec<_> = self.errors.iter().collect();
+        errors.sort_by_key(|error| error.line());
+        for (index, line) in errors.chunk_by(|a, b| a.line() == b.line()).enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            match line {
+                [error] => write!(f, "{error}")?,
+                _ => {
+                    write!(f, "[line {}] Errors:", line[0].line())?;
+                    for error in line {
+                        write!(f, "\n  ")?;
+                        error.message(f)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ScanErrors {}
+
+pub fn scan_tokens(source: &[u8]) -> Result<Vec<Token>, ScanErrors> {
+    scan_tokens_with_options(source, &ScannerOptions::default())
+}
+
+pub fn scan_tokens_with_options(
+    source: &[u8],
+    options: &ScannerOptions,
+) -> Result<Vec<Token>, ScanErrors> {
+    let mut tokens = Vec::new();
+    scan_tokens_into(source, options, &mut tokens).map(|()| tokens)
+}
+
+/// Like [`scan_tokens_with_options`], but replaces the contents of `tokens`
+/// instead of allocating a new vector, so that callers scanning one input
+/// after another can reuse its capacity.
+pub fn scan_tokens_into(
+    source: &[u8],
+    options: &ScannerOptions,
+    tokens: &mut Vec<Token>,
+) -> Result<(), ScanErrors> {
+    tokens.clear();
+    if let Some(max) = options.max_source_size.filter(|&max| source.len() > max) {
+        return Err(ScanErrors {
+            errors: vec![ScanError::LimitExceeded {
+                line: 1,
+                limit: Limit::SourceSize,
+                max,
+            }],
+        });
+    }
+    let mut scanner = Scanner::new(source, options, std::mem::take(tokens));
+    // Editors on Windows commonly save files with a UTF-8 byte order mark.
+    // Skipping it rather than slicing it off keeps spans true to `source`.
+    if source.starts_with(b"\xEF\xBB\xBF") {
+        scanner.current = 3;
+        scanner.line_start = 3;
+    }
+    scanner.run(tokens)
+}
+
+/// Classifies every byte of `source`. Unlike [`scan_tokens`], which keeps
+/// only what the parser needs, the pieces returned cover the whole input
+/// exactly and in order, which is what formatters and highlighters need.
+/// Errors do not stop the scan; they become pieces of their own.
+pub fn scan_lossless(source: &[u8]) -> Vec<Piece> {
+    let options = ScannerOptions {
+        keep_comments: true,
+        ..ScannerOptions::default()
+    };
+    let mut scanner = Scanner::new(source, &options, Vec::new());
+    let mut pieces = Vec::new();
+    if source.starts_with(b"\xEF\xBB\xBF") {
+        scanner.current = 3;
+        scanner.line_start = 3;
+        pieces.push(Piece {
+            kind: PieceKind::Whitespace,
+            span: Span {
+                start: 0,
+                end: 3,
+                line: 1,
+                column: 1,
+            },
+        });
+    }
+    while !scanner.is_at_end() {
+        let (tokens, errors) = (scanner.tokens.len(), scanner.errors.len());
+        scanner.step();
+        let text = &source[scanner.start..scanner.current];
+        let kind = if let Some(error) = scanner.errors.get(errors) {
+            PieceKind::Error(error.clone())
+        } else if let Some(token) = scanner.tokens.get(tokens) {
+            match token.token_type {
+                TokenType::Comment => PieceKind::Comment,
+                ref token_type => PieceKind::Token(token_type.clone()),
+            }
+        } else if text.starts_with(b"//") {
+            // The only comments that produce no token are doc comments.
+            PieceKind::DocComment
+        } else {
+            PieceKind::Whitespace
+        };
+        let span = Span {
+            start: scanner.start,
+            end: scanner.current,
+            line: scanner.start_line,
+            column: scanner.start - scanner.start_line_start + 1,
+        };
+        match pieces.last_mut() {
+            Some(last) if last.kind == PieceKind::Whitespace && kind == PieceKind::Whitespace => {
+                last.span.end = span.end;
+            }
+            _ => pieces.push(Piece { kind, span }),
+        }
+    }
+    pieces
+}
+
+/// Scans the `range` of `source` as if nothing else were there, for
+/// re-scanning part of a file after an edit. `range` must start between
+/// tokens. Spans are positions in the whole of `source`.
+pub(crate) fn scan_range(source: &[u8], range: Range<usize>) -> Result<Vec<Token>, ScanErrors> {
+    if range.start == 0 {
+        return scan_tokens(&source[..range.end]);
+    }
+    let options = ScannerOptions::default();
+    let mut scanner = Scanner::new(&source[..range.end], &options, Vec::new());
+    let before = &source[..range.start];
+    scanner.current = range.start;
+    scanner.line += before.iter().filter(|&&byte| byte == b'\n').count();
+    scanner.line_start = before
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let mut tokens = Vec::new();
+    scanner.run(&mut tokens).map(|()| tokens)
+}
+
+struct Scanner<'a> {
+    source: &'a [u8],
+    options: &'a ScannerOptions,
+    tokens: Vec<Token>,
+    errors: Vec<ScanError>,
+    start: usize,
+    current: usize,
+    line: usize,
+    /// Offset of the first byte of the current line.
+    line_start: usize,
+    /// Line and line offset on which the token being scanned starts.
+    start_line: usize,
+    start_line_start: usize,
+    doc: Option<String>,
+    /// Set once a limit stops the scan; the rest of the source is ignored.
+    halted: bool,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a [u8], options: &'a ScannerOptions, tokens: Vec<Token>) -> Self {
+        Self {
+            source,
+            options,
+            tokens,
+            errors: Vec::new(),
+            start: 0,
+            current: 0,
+            line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_line_start: 0,
+            doc: None,
+            halted: false,
+        }
+    }
+
+    /// Scans from `current` to the end of the source into `tokens`.
+    fn run(mut self, tokens: &mut Vec<Token>) -> Result<(), ScanErrors> {
+        while !self.is_at_end() {
+            self.step();
+        }
+        self.tokens.push(Token {
+            token_type: TokenType::Eof,
+            lexeme: String::new(),
+            span: Span {
+                start: self.current,
+                end: self.current,
+                line: self.line,
+                column: self.current - self.line_start + 1,
+            },
+            doc: self.doc.take(),
+        });
+        *tokens = self.tokens;
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ScanErrors {
+                errors: self.errors,
+            })
+        }
+    }
+
+    /// Scans whatever starts at `current`: a token, a comment, whitespace or
+    /// an error.
+    fn step(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_line_start = self.line_start;
+        self.scan_token();
+    }
+
+    fn scan_token(&mut self) {
+        match self.advance() {
+            b'(' => self.add_token(TokenType::LeftBracket),
+            b')' => self.add_token(TokenType::RightBracket),
+            b'{' => self.add_token(TokenType::LeftBrace),
+            b'}' => self.add_token(TokenType::RightBrace),
+            b':' => self.add_token(TokenType::Colon),
+            b',' => self.add_token(TokenType::Comma),
+            b'.' if self.peek() == b'.' && self.peek_next() == b'.' => {
+                self.current += 2;
+                self.add_token(TokenType::Ellipsis);
+            }
+            b'.' => self.add_token(TokenType::Dot),
+            b'-' => self.add_token(TokenType::Minus),
+            b'+' => self.add_token(TokenType::Plus),
+            b';' => self.add_token(TokenType::Semicolon),
+            b'*' => self.add_token(TokenType::Star),
+            b'%' => self.add_token(TokenType::Percent),
+            b'!' => {
+                let token_type = if self.matches(b'=') {
+                    TokenType::BangEqual
+                } else {
+                    TokenType::Bang
+                };
+                self.add_token(token_type);
+            }
+            b'=' => {
+                let token_type = if self.matches(b'=') {
+                    TokenType::EqualEqual
+                } else {
+                    TokenType::Equal
+                };
+                self.add_token(token_type);
+            }
+            b'<' => {
+                let token_type = if self.matches(b'=') {
+                    TokenType::LessEqual
+                } else {
+                    TokenType::Less
+                };
+                self.add_token(token_type);
+            }
+            b'>' => {
+                let token_type = if self.matches(b'=') {
+                    TokenType::GreaterEqual
+                } else {
+                    TokenType::Greater
+                };
+                self.add_token(token_type);
+            }
+            b'/' => {
+                if self.matches(b'/') {
+                    self.comment();
+                } else {
+                    self.add_token(TokenType::Slash);
+                }
+            }
+            b' ' | b'\r' | b'\t' => (),
+            b'\n' => {
+                // A blank line separates documentation from what follows.
+                let line = &self.source[self.line_start..self.current - 1];
+                if line.iter().all(|c| matches!(c, b' ' | b'\r' | b'\t')) {
+                    self.doc = None;
+                }
+                self.new_line();
+            }
+            b'"' => self.string(),
+            b'\'' => self.char(),
+            c if c.is_ascii_digit() => self.number(),
+            c if is_alpha(c) => self.identifier(),
+            c => self.errors.push(ScanError::UnexpectedCharacter {
+                line: self.line,
+                character: c,
+            }),
+        }
+    }
+
+    fn comment(&mut self) {
+        while self.peek() != b'\n' && !self.is_at_end() {
+            self.advance();
+        }
+        // `///` starts a doc comment, but `////` and longer are ordinary
+        // comments, typically used as visual separators.
+        let text = String::from_utf8_lossy(&self.source[self.start + 2..self.current]);
+        if let Some(doc) = text.strip_prefix('/').filter(|doc| !doc.starts_with('/')) {
+            let doc = doc.strip_prefix(' ').unwrap_or(doc);
+            match &mut self.doc {
+                Some(lines) => {
+                    lines.push('\n');
+                    lines.push_str(doc);
+                }
+                None => self.doc = Some(doc.to_owned()),
+            }
+        } else if self.options.keep_comments {
+            self.add_token(TokenType::Comment);
+        }
+    }
+
+    fn string(&mut self) {
+        while self.peek() != b'"' && !self.is_at_end() {
+            if self.advance() == b'\n' {
+                self.new_line();
+            }
+        }
+        if self.is_at_end() {
+            self.errors
+                .push(ScanError::UnterminatedString { line: self.line });
+            return;
+        }
+        // The closing ".
+        self.advance();
+        let length = self.current - self.start - 2;
+        if let Some(max) = self.options.max_string_length.filter(|&max| length > max) {
+            self.errors.push(ScanError::LimitExceeded {
+                line: self.line,
+                limit: Limit::StringLength,
+                max,
+            });
+            return;
+        }
+        match str::from_utf8(&self.source[self.start + 1..self.current - 1]) {
+            Ok(value) => self.add_token(TokenType::String(value.to_owned())),
+            Err(_) => self.errors.push(ScanError::InvalidUtf8 { line: self.line }),
+        }
+    }
+
+    fn char(&mut self) {
+        while self.peek() != b'\'' && self.peek() != b'\n' && !self.is_at_end() {
+            // Skip the escaped character so that '\'' does not end the
+            // literal, unless it is a line break: the literal ends there.
+            if self.advance() == b'\\' && self.peek() != b'\n' && !self.is_at_end() {
+                self.advance();
+            }
+        }
+        if self.peek() != b'\'' {
+            self.errors
+                .push(ScanError::UnterminatedChar { line: self.line });
+            return;
+        }
+        // The closing '.
+        self.advance();
+        let Ok(text) = str::from_utf8(&self.source[self.start + 1..self.current - 1]) else {
+            self.errors.push(ScanError::InvalidUtf8 { line: self.line });
+            return;
+        };
+        let c = match text.strip_prefix('\\') {
+            Some(escape) => match escape {
+                "n" => Some('\n'),
+                "r" => Some('\r'),
+                "t" => Some('\t'),
+                "0" => Some('\0'),
+                "\\" => Some('\\'),
+                "'" => Some('\''),
+                "\"" => Some('"'),
+                _ => {
+                    self.errors
+                        .push(ScanError::InvalidEscape { line: self.line });
+                    return;
+                }
+            },
+            None => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => None,
+                }
+            }
+        };
+        match c {
+            Some(c) => self.add_token(TokenType::Char(c)),
+            None => self.errors.push(ScanError::InvalidChar { line: self.line }),
+        }
+    }
+
+    fn number(&mut self) {
+        while self.peek().is_ascii_digit() {
+            self.advance();
+        }
+        // Look for a fractional part.
+        if self.peek() == b'.' && self.peek_next().is_ascii_digit() {
+            // Consume the ".".
+            self.advance();
+            while self.peek().is_ascii_digit() {
+                self.advance();
+            }
+        }
+        let number = str::from_utf8(&self.source[self.start..self.current])
+            .ok()
+            .and_then(|number| number.parse().ok());
+        match number {
+            Some(number) => self.add_token(TokenType::Number(number)),
+            None => self
+                .errors
+                .push(ScanError::InvalidNumber { line: self.line }),
+        }
+    }
+
+    fn identifier(&mut self) {
+        while is_alpha_numeric(self.peek()) {
+            self.advance();
+        }
+        let token_type = str::from_utf8(&self.source[self.start..self.current])
+            .ok()
+            .and_then(|text| KEYWORDS.get(text))
+            .cloned()
+            .unwrap_or(TokenType::Identifier);
+        self.add_token(token_type);
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.halted || self.current >= self.source.len()
+    }
+
+    fn advance(&mut self) -> u8 {
+        let c = self.source[self.current];
+        self.current += 1;
+        c
+    }
+
+    fn matches(&mut self, expected: u8) -> bool {
+        if self.is_at_end() || self.source[self.current] != expected {
+            return false;
+        }
+        self.current += 1;
+        true
+    }
+
+    fn peek(&self) -> u8 {
+        self.source.get(self.current).copied().unwrap_or(b'\0')
+    }
+
+    fn peek_next(&self) -> u8 {
+        self.source.get(self.current + 1).copied().unwrap_or(b'\0')
+    }
+
+    fn add_token(&mut self, token_type: TokenType) {
+        if let Some(max) = self
+            .options
+            .max_tokens
+            .filter(|&max| self.tokens.len() >= max)
+        {
+            self.errors.push(ScanError::LimitExceeded {
+                line: self.line,
+                limit: Limit::Tokens,
+                max,
+            });
+            self.halted = true;
+            return;
+        }
+        // Pending documentation belongs to the next real token.
+        let doc = match token_type {
+            TokenType::Comment => None,
+            _ => self.doc.take(),
+        };
+        self.tokens.push(Token {
+            token_type,
+            lexeme: String::from_utf8_lossy(&self.source[self.start..self.current]).into_owned(),
+            span: Span {
+                start: self.start,
+                end: self.current,
+                line: self.start_line,
+                column: self.start - self.start_line_start + 1,
+            },
+            doc,
+        });
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+}
+
+fn is_alpha(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn is_alpha_numeric(c: u8) -> bool {
+    is_alpha(c) || c.is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_types(source: &str) -> Vec<TokenType> {
+        scan_tokens(source.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect()
+    }
+
+    fn error_lines(source: &str) -> Vec<usize> {
+        scan_tokens(source.as_bytes())
+            .unwrap_err()
+            .errors()
+            .iter()
+            .map(ScanError::line)
+            .collect()
+    }
+
+    #[test]
+    fn char_literals_take_escapes() {
+        assert_eq!(
+            token_types(r"'a' '\n' '\'' '\\' 'é'"),
+            [
+                TokenType::Char('a'),
+                TokenType::Char('\n'),
+                TokenType::Char('\''),
+                TokenType::Char('\\'),
+                TokenType::Char('é'),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn char_literal_rejects_bad_contents() {
+        assert!(matches!(
+            scan_tokens(b"'ab'").unwrap_err().errors(),
+            [ScanError::InvalidChar { line: 1 }]
+        ));
+        assert!(matches!(
+            scan_tokens(br"'\q'").unwrap_err().errors(),
+            [ScanError::InvalidEscape { line: 1 }]
+        ));
+    }
+
+    #[test]
+    fn backslash_before_line_break_ends_char_literal() {
+        assert!(matches!(
+            scan_tokens(b"'\\\nvar").unwrap_err().errors(),
+            [ScanError::UnterminatedChar { line: 1 }]
+        ));
+        // The line break still counts, so later errors have the right line.
+        assert_eq!(error_lines("'\\\n\n@"), [1, 3]);
+    }
+
+    #[test]
+    fn reports_every_error_in_order() {
+        assert_eq!(error_lines("@ #\nvar\n$\n\"open\n\n"), [1, 1, 3, 6]);
+        let errors = scan_tokens(b"@ #").unwrap_err();
+        assert!(matches!(
+            errors.errors(),
+            [
+                ScanError::UnexpectedCharacter {
+                    line: 1,
+                    character: b'@'
+                },
+                ScanError::UnexpectedCharacter {
+                    line: 1,
+                    character: b'#'
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn display_groups_errors_by_line() {
+        let errors = scan_tokens(b"@ #\nvar\n$\n\"open\n\n").unwrap_err();
+        assert_eq!(
+            errors.to_string(),
+            "[line 1] Errors:\n  \
+             Unexpected character '@'.\n  \
+             Unexpected character '#'.\n\
+             [line 3] Error: Unexpected character '$'.\n\
+             [line 6] Error: Unterminated string."
+        );
+    }
+
+    #[test]
+    fn word_operators_scan_as_their_symbols() {
+        assert_eq!(
+            token_types("a is b mod c not d"),
+            [
+                TokenType::Identifier,
+                TokenType::EqualEqual,
+                TokenType::Identifier,
+                TokenType::Percent,
+                TokenType::Identifier,
+                TokenType::Bang,
+                TokenType::Identifier,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(
+            token_types("model isX note"),
+            [
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_a_leading_byte_order_mark() {
+        let source = "var x = \"é\";\nprint x;";
+        let with_bom = [&b"\xEF\xBB\xBF"[..], source.as_bytes()].concat();
+        let plain = scan_tokens(source.as_bytes()).unwrap();
+        let tokens = scan_tokens(&with_bom).unwrap();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| &token.token_type)
+                .collect::<Vec<_>>(),
+            plain
+                .iter()
+                .map(|token| &token.token_type)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!((tokens[0].span.line, tokens[0].span.column), (1, 1));
+        // Spans still index the bytes as given, BOM and all.
+        for token in &tokens[..tokens.len() - 1] {
+            assert_eq!(
+                &with_bom[token.span.start..token.span.end],
+                token.lexeme.as_bytes()
+            );
+        }
+        // Later lines are unaffected.
+        assert_eq!((tokens[5].span.line, tokens[5].span.column), (2, 1));
+    }
+
+    #[test]
+    fn a_byte_order_mark_after_the_start_is_an_error() {
+        let errors = scan_tokens(b"print 1;\n\xEF\xBB\xBFprint 2;").unwrap_err();
+        assert!(matches!(
+            errors.errors(),
+            [
+                ScanError::UnexpectedCharacter {
+                    line: 2,
+                    character: 0xEF
+                },
+                ..
+            ]
+        ));
+    }
+
+    /// Scans `source` with `options`, returning the limit it exceeded, if
+    /// any, and the maximum that limit was set to.
+    fn exceeded(source: &str, options: ScannerOptions) -> Option<(Limit, usize)> {
+        let errors = match scan_tokens_with_options(source.as_bytes(), &options) {
+            Ok(_) => return None,
+            Err(errors) => errors,
+        };
+        match errors.errors() {
+            [ScanError::LimitExceeded { limit, max, .. }] => Some((*limit, *max)),
+            errors => panic!("unexpected errors: {errors:?}"),
+        }
+    }
+
+    #[test]
+    fn limits_the_source_size() {
+        let options = |max| ScannerOptions {
+            max_source_size: Some(max),
+            ..ScannerOptions::default()
+        };
+        assert_eq!(exceeded("print 1;", options(8)), None);
+        assert_eq!(
+            exceeded("print 1;", options(7)),
+            Some((Limit::SourceSize, 7))
+        );
+    }
+
+    #[test]
+    fn limits_the_token_count() {
+        // `print 1;` is three tokens; the final `Eof` does not count.
+        let options = |max| ScannerOptions {
+            max_tokens: Some(max),
+            ..ScannerOptions::default()
+        };
+        assert_eq!(exceeded("print 1;", options(3)), None);
+        assert_eq!(exceeded("print 1;", options(2)), Some((Limit::Tokens, 2)));
+    }
+
+    #[test]
+    fn limits_the_length_of_strings() {
+        let options = |max| ScannerOptions {
+            max_string_length: Some(max),
+            ..ScannerOptions::default()
+        };
+        // The quotes do not count, and the limit is in bytes.
+        assert_eq!(exceeded("\"abcé\"", options(5)), None);
+        assert_eq!(
+            exceeded("\"abcé\"", options(4)),
+            Some((Limit::StringLength, 4))
+        );
+    }
+
+    fn docs(source: &str) -> Vec<(String, Option<String>)> {
+        scan_tokens(source.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|token| (token.lexeme, token.doc))
+            .collect()
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_next_token() {
+        assert_eq!(
+            docs("/// Adds.\n///\n///Twice.\nfun"),
+            [
+                ("fun".to_owned(), Some("Adds.\n\nTwice.".to_owned())),
+                (String::new(), None),
+            ]
+        );
+        // Only the next token gets them, declaration or not.
+        assert_eq!(
+            docs("/// Not for f.\nprint 1; fun f"),
+            [
+                ("print".to_owned(), Some("Not for f.".to_owned())),
+                ("1".to_owned(), None),
+                (";".to_owned(), None),
+                ("fun".to_owned(), None),
+                ("f".to_owned(), None),
+                (String::new(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_comments_are_not_documentation() {
+        assert_eq!(
+            docs("// Plain.\n//// Rule.\nfun"),
+            [("fun".to_owned(), None), (String::new(), None)]
+        );
+    }
+
+    #[test]
+    fn a_blank_line_drops_documentation() {
+        assert_eq!(
+            docs("/// Lost.\n\nfun /// Kept.\n  \t\n/// Also kept.\nclass"),
+            [
+                ("fun".to_owned(), None),
+                ("class".to_owned(), Some("Also kept.".to_owned())),
+                (String::new(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lossless_pieces_cover_the_source_exactly() {
+        let source = b"\xEF\xBB\xBF// Note.\n/// Doc.\nvar  x = 1;\t@\n\"open";
+        let pieces = scan_lossless(source);
+        let mut end = 0;
+        for piece in &pieces {
+            assert_eq!(piece.span.start, end, "a gap or overlap before {piece:?}");
+            end = piece.span.end;
+        }
+        assert_eq!(end, source.len());
+        let kinds: Vec<_> = pieces.iter().map(|piece| &piece.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                &PieceKind::Whitespace,
+                &PieceKind::Comment,
+                &PieceKind::Whitespace,
+                &PieceKind::DocComment,
+                &PieceKind::Whitespace,
+                &PieceKind::Token(TokenType::Var),
+                &PieceKind::Whitespace,
+                &PieceKind::Token(TokenType::Identifier),
+                &PieceKind::Whitespace,
+                &PieceKind::Token(TokenType::Equal),
+                &PieceKind::Whitespace,
+                &PieceKind::Token(TokenType::Number(1.0)),
+                &PieceKind::Token(TokenType::Semicolon),
+                &PieceKind::Whitespace,
+                &PieceKind::Error(ScanError::UnexpectedCharacter {
+                    line: 3,
+                    character: b'@'
+                }),
+                &PieceKind::Whitespace,
+                &PieceKind::Error(ScanError::UnterminatedString { line: 4 }),
+            ]
+        );
+        // The BOM joins the whitespace, so the first piece is just it.
+        assert_eq!(pieces[0].span.end, 3);
+        let joined: Vec<u8> = pieces
+            .iter()
+            .flat_map(|piece| &source[piece.span.start..piece.span.end])
+            .copied()
+            .collect();
+        assert_eq!(joined, source);
+    }
+}
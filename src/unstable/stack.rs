@@ -0,0 +1,14 @@
+//! Recursing as deeply as the code being processed, without overflowing
+//! the thread's stack.
+
+// Rather than overflow the stack, recursion continues on a new segment of
+// `STACK_SEGMENT` bytes whenever less than `RED_ZONE` bytes are left.
+const RED_ZONE: usize = 128 * 1024;
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+/// Runs `f`, on a new stack segment if the current one is running out.
+/// Functions that recurse once per level of nesting call this at each
+/// level.
+pub(crate) fn grow<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
@@ -0,0 +1,276 @@
+//! Code-quality figures for a script, as reported by `lox stats`.
+
+use std::{collections::HashSet, fmt};
+
+use crate::ast::{ExprId, ExprKind, Function, Program, Statement, StatementKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Every function and method, in source order. Methods are named
+    /// `Class.method`.
+    pub functions: Vec<FunctionStats>,
+    /// How deeply statements nest inside functions, `if`s and loops, with
+    /// top-level statements at depth 1. A block alone adds no level.
+    pub max_depth: usize,
+    /// Lines holding a comment, counting each line of documentation.
+    pub comment_lines: usize,
+    /// Lines that are not blank.
+    pub lines: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionStats {
+    pub name: String,
+    pub line: usize,
+    /// Lines from the declaration to the closing brace, inclusive.
+    pub length: usize,
+    /// One more than the number of decision points: `if`, `while`, `for`,
+    /// `and` and `or`.
+    pub complexity: usize,
+}
+
+impl Stats {
+    /// Measures `program`, parsed from `source`. Comments are only counted
+    /// if the program came from
+    /// [`parse_lossless`](crate::parser::parse_lossless).
+    pub fn new(source: &[u8], program: &Program) -> Self {
+        let comment_lines: HashSet<_> = program
+            .comments
+            .iter()
+            .map(|comment| comment.span.line)
+            .collect();
+        let mut stats = Self {
+            functions: Vec::new(),
+            max_depth: 0,
+            comment_lines: comment_lines.len(),
+            lines: source
+                .split(|&byte| byte == b'\n')
+                .filter(|line| !line.trim_ascii().is_empty())
+                .count(),
+        };
+        let mut walker = Walker {
+            source,
+            program,
+            stats: &mut stats,
+        };
+        for statement in &program.statements {
+            walker.statement(statement, 1, None);
+        }
+        stats
+    }
+
+    pub fn longest_function(&self) -> Option<&FunctionStats> {
+        // Ties go to the function declared first.
+        self.functions
+            .iter()
+            .rev()
+            .max_by_key(|function| function.length)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "functions: {}", self.functions.len())?;
+        writeln!(f, "max nesting depth: {}", self.max_depth)?;
+        if let Some(function) = self.longest_function() {
+            writeln!(
+                f,
+                "longest function: {} ({} lines)",
+                function.name, function.length
+            )?;
+        }
+        let percent = match self.lines {
+            0 => 0,
+            lines => self.comment_lines * 100 / lines,
+        };
+        write!(
+            f,
+            "comment ratio: {percent}% ({} of {} lines)",
+            self.comment_lines, self.lines
+        )?;
+        if !self.functions.is_empty() {
+            write!(f, "\ncyclomatic complexity:")?;
+            for function in &self.functions {
+                write!(
+                    f,
+                    "\n  {} (line {}): {}",
+                    function.name, function.line, function.complexity
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Walker<'a> {
+    source: &'a [u8],
+    program: &'a Program,
+    stats: &'a mut Stats,
+}
+
+impl Walker<'_> {
+    /// Visits `statement`, which sits at `depth` inside the function at
+    /// index `function` of `stats.functions`, if any.
+    fn statement(&mut self, statement: &Statement, depth: usize, function: Option<usize>) {
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+        let mut decisions = statement
+            .exprs()
+            .into_iter()
+            .map(|expr| self.decisions(expr))
+            .sum::<usize>();
+        match &statement.kind {
+            StatementKind::Class {
+                name,
+                fields,
+                methods,
+                doc,
+                ..
+            } => {
+                self.comments(doc.as_deref());
+                for field in fields {
+                    decisions += self.decisions(field.initializer);
+                }
+                for method in methods {
+                    let name = format!("{}.{}", name.token.lexeme, method.name.token.lexeme);
+                    self.function(name, method, depth);
+                }
+            }
+            StatementKind::Function(declaration) => {
+                let name = declaration.name.token.lexeme.clone();
+                self.function(name, declaration, depth);
+            }
+            kind => {
+                let depth = match kind {
+                    StatementKind::Block(_) => depth,
+                    StatementKind::For { .. }
+                    | StatementKind::If { .. }
+                    | StatementKind::Try { catch: Some(_), .. }
+                    | StatementKind::While { .. } => {
+                        decisions += 1;
+                        depth + 1
+                    }
+                    _ => depth + 1,
+                };
+                for child in statement.children() {
+                    self.statement(child, depth, function);
+                }
+            }
+        }
+        if let Some(function) = function {
+            self.stats.functions[function].complexity += decisions;
+        }
+    }
+
+    fn function(&mut self, name: String, function: &Function, depth: usize) {
+        self.comments(function.doc.as_deref());
+        let text = &self.source[function.span.start..function.span.end];
+        self.stats.functions.push(FunctionStats {
+            name,
+            line: function.span.line,
+            length: text.iter().filter(|&&byte| byte == b'\n').count() + 1,
+            complexity: 1,
+        });
+        let index = self.stats.functions.len() - 1;
+        for statement in &function.body {
+            self.statement(statement, depth + 1, Some(index));
+        }
+    }
+
+    fn comments(&mut self, doc: Option<&str>) {
+        self.stats.comment_lines += doc.map_or(0, |doc| doc.lines().count());
+    }
+
+    /// Counts the `and` and `or` operators in `expr`.
+    fn decisions(&self, expr: ExprId) -> usize {
+        let kind = &self.program[expr].kind;
+        let own = usize::from(matches!(kind, ExprKind::Logical { .. }));
+        own + kind
+            .children()
+            .into_iter()
+            .map(|child| self.decisions(child))
+            .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::parse_lossless,
+        scanner::{scan_tokens_with_options, ScannerOptions},
+    };
+
+    const SCRIPT: &str = "\
+// Counts things.
+/// Adds one.
+fun add(a) {
+  return a + 1;
+}
+
+class Counter {
+  /// Steps.
+  step(n) {
+    if (n > 0 and n < 10) {
+      while (n > 0) n = n - 1;
+    }
+    return n;
+  }
+}
+
+print add(1); // Two.
+";
+
+    fn stats(source: &str) -> Stats {
+        let options = ScannerOptions {
+            keep_comments: true,
+            ..ScannerOptions::default()
+        };
+        let tokens = scan_tokens_with_options(source.as_bytes(), &options).unwrap();
+        Stats::new(source.as_bytes(), &parse_lossless(tokens).unwrap())
+    }
+
+    #[test]
+    fn measures_a_script() {
+        let stats = stats(SCRIPT);
+        assert_eq!(
+            stats.functions,
+            [
+                FunctionStats {
+                    name: "add".to_owned(),
+                    line: 3,
+                    length: 3,
+                    complexity: 1,
+                },
+                FunctionStats {
+                    name: "Counter.step".to_owned(),
+                    line: 9,
+                    length: 6,
+                    // `if`, `and` and `while`.
+                    complexity: 4,
+                },
+            ]
+        );
+        // The assignment in the `while` in the `if` in the method.
+        assert_eq!(stats.max_depth, 4);
+        // Two comments and two lines of documentation.
+        assert_eq!((stats.comment_lines, stats.lines), (4, 15));
+        assert_eq!(stats.longest_function().unwrap().name, "Counter.step");
+        assert_eq!(
+            stats.to_string(),
+            "functions: 2\n\
+             max nesting depth: 4\n\
+             longest function: Counter.step (6 lines)\n\
+             comment ratio: 26% (4 of 15 lines)\n\
+             cyclomatic complexity:\n  \
+             add (line 3): 1\n  \
+             Counter.step (line 9): 4"
+        );
+    }
+
+    #[test]
+    fn ties_for_the_longest_function_go_to_the_first() {
+        let stats = stats("fun a() {}\nfun b() {}");
+        assert_eq!(stats.longest_function().unwrap().name, "a");
+        assert_eq!(stats.max_depth, 1);
+    }
+}
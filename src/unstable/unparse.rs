@@ -0,0 +1,632 @@
+//! Turns the AST back into Lox source. Parsing the output yields the same
+//! tree, so tools can transform a program and write the result out again.
+//!
+//! Layout is normalized: one statement per line, four-space indentation.
+//! Parentheses are emitted for [`ExprKind::Grouping`] nodes and wherever
+//! precedence requires them, which only matters for trees built by hand.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    ast::{
+        BinaryOp, Comment, CommentPlacement, ExprId, ExprKind, Function, LogicalOp, Program,
+        Statement, StatementId, StatementKind,
+    },
+    scanner::Token,
+};
+
+/// Renders a whole program, including any comments kept by
+/// [`parse_lossless`](crate::parser::parse_lossless).
+pub fn unparse_program(program: &Program) -> String {
+    let mut unparser = Unparser::new(program);
+    for comment in program
+        .comments
+        .iter()
+        .filter(|comment| comment.statement.is_none())
+    {
+        unparser.line(&format!("//{}", comment.text));
+    }
+    for statement in &program.statements {
+        unparser.statement(statement);
+    }
+    if let Some(result) = program.result {
+        let result = unparse_expr(program, result);
+        unparser.line(&result);
+    }
+    unparser.output
+}
+
+pub fn unparse_statement(program: &Program, statement: &Statement) -> String {
+    let mut unparser = Unparser::new(program);
+    unparser.statement(statement);
+    unparser.output
+}
+
+pub fn unparse_expr(program: &Program, expr: ExprId) -> String {
+    match &program[expr].kind {
+        ExprKind::Assign { name, value } => {
+            format!("{} = {}", name.token.lexeme, unparse_expr(program, *value))
+        }
+        ExprKind::AssignTuple { targets, value } => format!(
+            "{} = {}",
+            tuple(targets.iter().map(|target| unparse_expr(program, *target))),
+            unparse_expr(program, *value)
+        ),
+        ExprKind::Binary {
+            left,
+            operator,
+            right,
+        } => infix(program, expr, *left, operator, *right),
+        ExprKind::Call {
+            callee, arguments, ..
+        } => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| unparse_expr(program, *argument))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "{}({arguments})",
+                operand(program, *callee, Precedence::Call)
+            )
+        }
+        ExprKind::Get { object, name } => {
+            format!(
+                "{}.{}",
+                operand(program, *object, Precedence::Call),
+                name.token.lexeme
+            )
+        }
+        ExprKind::Grouping(expr) => format!("({})", unparse_expr(program, *expr)),
+        ExprKind::Literal(literal) => literal.token.lexeme.clone(),
+        ExprKind::Logical {
+            left,
+            operator,
+            right,
+        } => infix(program, expr, *left, operator, *right),
+        ExprKind::Set {
+            object,
+            name,
+            value,
+        } => format!(
+            "{}.{} = {}",
+            operand(program, *object, Precedence::Call),
+            name.token.lexeme,
+            unparse_expr(program, *value)
+        ),
+        ExprKind::Super { method, .. } => format!("super.{}", method.token.lexeme),
+        ExprKind::This(_) => "this".to_owned(),
+        ExprKind::Tuple(elements) => tuple(
+            elements
+                .iter()
+                .map(|element| unparse_expr(program, *element)),
+        ),
+        ExprKind::Unary { operator, right } => {
+            format!("{operator}{}", operand(program, *right, Precedence::Unary))
+        }
+        ExprKind::Variable(name) => name.token.lexeme.clone(),
+    }
+}
+
+/// How tightly each kind of expression binds, from loosest to tightest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    fn of(kind: &ExprKind) -> Self {
+        match kind {
+            ExprKind::Assign { .. } | ExprKind::AssignTuple { .. } | ExprKind::Set { .. } => {
+                Self::Assignment
+            }
+            ExprKind::Binary { operator, .. } => match operator {
+                BinaryOp::Equal | BinaryOp::NotEqual => Self::Equality,
+                BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::Less
+                | BinaryOp::LessEqual => Self::Comparison,
+                BinaryOp::Add | BinaryOp::Subtract => Self::Term,
+                BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Remainder => Self::Factor,
+            },
+            ExprKind::Logical { operator, .. } => match operator {
+                LogicalOp::Or => Self::Or,
+                LogicalOp::And => Self::And,
+            },
+            ExprKind::Unary { .. } => Self::Unary,
+            ExprKind::Call { .. } | ExprKind::Get { .. } => Self::Call,
+            ExprKind::Grouping(_)
+            | ExprKind::Literal(_)
+            | ExprKind::Super { .. }
+            | ExprKind::This(_)
+            | ExprKind::Tuple(_)
+            | ExprKind::Variable(_) => Self::Primary,
+        }
+    }
+}
+
+/// Renders `expr`, a left-associative infix expression.
+fn infix(
+    program: &Program,
+    expr: ExprId,
+    left: ExprId,
+    operator: impl fmt::Display,
+    right: ExprId,
+) -> String {
+    let precedence = Precedence::of(&program[expr].kind);
+    let left = operand(program, left, precedence);
+    // An operand on the right of equal precedence needs parentheses:
+    // `a - (b - c)` is not `a - b - c`.
+    let right = match Precedence::of(&program[right].kind) {
+        inner if inner <= precedence => format!("({})", unparse_expr(program, right)),
+        _ => unparse_expr(program, right),
+    };
+    format!("{left} {operator} {right}")
+}
+
+/// Renders `expr`, parenthesized if it binds more loosely than `min`.
+fn operand(program: &Program, expr: ExprId, min: Precedence) -> String {
+    if Precedence::of(&program[expr].kind) < min {
+        format!("({})", unparse_expr(program, expr))
+    } else {
+        unparse_expr(program, expr)
+    }
+}
+
+/// Renders a tuple of `elements`, with the comma a lone element needs.
+fn tuple(elements: impl Iterator<Item = String>) -> String {
+    let elements = elements.collect::<Vec<_>>();
+    match elements.as_slice() {
+        [element] => format!("({element},)"),
+        elements => format!("({})", elements.join(", ")),
+    }
+}
+
+/// Renders the `label: ` in front of a loop, if it has one.
+fn label_prefix(label: Option<&Token>) -> String {
+    label.map_or_else(String::new, |label| format!("{}: ", label.lexeme))
+}
+
+struct Unparser<'a> {
+    program: &'a Program,
+    comments: HashMap<StatementId, Vec<&'a Comment>>,
+    output: String,
+    indent: usize,
+    /// Whether the next line continues the last one instead of starting
+    /// on a new, indented line.
+    continue_line: bool,
+    /// Text to put at the start of the next line, such as the `else` of
+    /// an `else if`.
+    prefix: &'static str,
+}
+
+impl<'a> Unparser<'a> {
+    fn new(program: &'a Program) -> Self {
+        let mut comments: HashMap<_, Vec<_>> = HashMap::new();
+        for comment in &program.comments {
+            if let Some(statement) = comment.statement {
+                comments.entry(statement).or_default().push(comment);
+            }
+        }
+        Self {
+            program,
+            comments,
+            output: String::new(),
+            indent: 0,
+            continue_line: false,
+            prefix: "",
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let comments = self.comments.remove(&statement.id).unwrap_or_default();
+        let placed = |placement| {
+            comments
+                .iter()
+                .filter(move |comment| comment.placement == placement)
+        };
+        for comment in placed(CommentPlacement::Leading) {
+            self.line(&format!("//{}", comment.text));
+        }
+        self.statement_code(statement);
+        for comment in placed(CommentPlacement::Trailing) {
+            self.output.pop();
+            self.output.push_str(&format!(" //{}\n", comment.text));
+        }
+        for comment in placed(CommentPlacement::End) {
+            self.line(&format!("//{}", comment.text));
+        }
+    }
+
+    fn statement_code(&mut self, statement: &Statement) {
+        let program = self.program;
+        match &statement.kind {
+            StatementKind::Assert {
+                condition, message, ..
+            } => match message {
+                Some(message) => self.line(&format!(
+                    "assert {}, {};",
+                    unparse_expr(program, *condition),
+                    unparse_expr(program, *message)
+                )),
+                None => self.line(&format!("assert {};", unparse_expr(program, *condition))),
+            },
+            StatementKind::Block(statements) => {
+                self.block("", statements);
+            }
+            StatementKind::Class {
+                name,
+                superclass,
+                fields,
+                methods,
+                doc,
+            } => {
+                self.doc(doc.as_deref());
+                let mut header = format!("class {}", name.token.lexeme);
+                if let Some(superclass) = superclass {
+                    header.push_str(&format!(" < {}", unparse_expr(program, *superclass)));
+                }
+                if fields.is_empty() && methods.is_empty() {
+                    self.line(&format!("{header} {{}}"));
+                    return;
+                }
+                self.line(&format!("{header} {{"));
+                self.indent += 1;
+                for field in fields {
+                    self.line(&format!(
+                        "{} = {};",
+                        field.name.token.lexeme,
+                        unparse_expr(program, field.initializer)
+                    ));
+                }
+                for method in methods {
+                    self.function("", method);
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            StatementKind::Break { label, .. } => match label {
+                Some(label) => self.line(&format!("break {};", label.lexeme)),
+                None => self.line("break;"),
+            },
+            StatementKind::Continue { label, .. } => match label {
+                Some(label) => self.line(&format!("continue {};", label.lexeme)),
+                None => self.line("continue;"),
+            },
+            StatementKind::Expression(expr) => {
+                self.line(&format!("{};", unparse_expr(program, *expr)));
+            }
+            StatementKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                label,
+            } => {
+                let mut header = label_prefix(label.as_ref());
+                header.push_str("for (");
+                match initializer.as_deref().map(|initializer| &initializer.kind) {
+                    Some(StatementKind::Var { name, initializer }) => {
+                        header.push_str(&format!("var {}", name.token.lexeme));
+                        if let Some(initializer) = initializer {
+                            header.push_str(&format!(" = {}", unparse_expr(program, *initializer)));
+                        }
+                    }
+                    Some(StatementKind::VarTuple { names, initializer }) => {
+                        header.push_str(&format!(
+                            "var {} = {}",
+                            tuple(names.iter().map(|name| name.token.lexeme.clone())),
+                            unparse_expr(program, *initializer)
+                        ));
+                    }
+                    Some(StatementKind::Expression(expr)) => {
+                        header.push_str(&unparse_expr(program, *expr));
+                    }
+                    // The parser only makes the kinds above.
+                    _ => {}
+                }
+                header.push(';');
+                if let Some(condition) = condition {
+                    header.push_str(&format!(" {}", unparse_expr(program, *condition)));
+                }
+                header.push(';');
+                if let Some(increment) = increment {
+                    header.push_str(&format!(" {}", unparse_expr(program, *increment)));
+                }
+                header.push(')');
+                self.branch(&header, body);
+            }
+            StatementKind::Function(function) => self.function("fun ", function),
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let header = format!("if ({})", unparse_expr(program, *condition));
+                // An `else` would bind to a nested `if` that has none of its
+                // own, so such a branch must be braced.
+                let dangling = else_branch.is_some()
+                    && matches!(
+                        then_branch.kind,
+                        StatementKind::If {
+                            else_branch: None,
+                            ..
+                        }
+                    );
+                if dangling {
+                    self.block(&header, std::slice::from_ref(then_branch));
+                } else {
+                    self.branch(&header, then_branch);
+                }
+                if let Some(else_branch) = else_branch {
+                    self.join_brace();
+                    match else_branch.kind {
+                        StatementKind::If { .. }
+                            if !self.comments.contains_key(&else_branch.id) =>
+                        {
+                            self.prefix = "else ";
+                            self.statement_code(else_branch);
+                        }
+                        _ => self.branch("else", else_branch),
+                    }
+                }
+            }
+            StatementKind::Print(expr) => {
+                self.line(&format!("print {};", unparse_expr(program, *expr)));
+            }
+            StatementKind::Return { value, .. } => match value {
+                Some(value) => self.line(&format!("return {};", unparse_expr(program, *value))),
+                None => self.line("return;"),
+            },
+            StatementKind::Throw { value, .. } => {
+                self.line(&format!("throw {};", unparse_expr(program, *value)));
+            }
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.branch("try", body);
+                if let Some(catch) = catch {
+                    self.join_brace();
+                    self.branch(&format!("catch ({})", catch.name.token.lexeme), &catch.body);
+                }
+                if let Some(finally) = finally {
+                    self.join_brace();
+                    self.branch("finally", finally);
+                }
+            }
+            StatementKind::Var { name, initializer } => match initializer {
+                Some(initializer) => self.line(&format!(
+                    "var {} = {};",
+                    name.token.lexeme,
+                    unparse_expr(program, *initializer)
+                )),
+                None => self.line(&format!("var {};", name.token.lexeme)),
+            },
+            StatementKind::VarTuple { names, initializer } => self.line(&format!(
+                "var {} = {};",
+                tuple(names.iter().map(|name| name.token.lexeme.clone())),
+                unparse_expr(program, *initializer)
+            )),
+            // Only a lowered `for` loop has an increment, which a `for`
+            // loop without an initializer renders exactly.
+            StatementKind::While {
+                condition,
+                body,
+                increment: Some(increment),
+                label,
+            } => {
+                let header = format!(
+                    "{}for (; {}; {})",
+                    label_prefix(label.as_ref()),
+                    unparse_expr(program, *condition),
+                    unparse_expr(program, *increment)
+                );
+                self.branch(&header, body);
+            }
+            StatementKind::While {
+                condition,
+                body,
+                increment: None,
+                label,
+            } => {
+                let header = format!(
+                    "{}while ({})",
+                    label_prefix(label.as_ref()),
+                    unparse_expr(program, *condition)
+                );
+                self.branch(&header, body);
+            }
+        }
+    }
+
+    /// Renders the body of an `if`, `else` or `while`: a block opens on the
+    /// header's line, and any other statement goes on the next line.
+    fn branch(&mut self, header: &str, body: &Statement) {
+        match &body.kind {
+            StatementKind::Block(statements) if !self.comments.contains_key(&body.id) => {
+                self.block(header, statements);
+            }
+            _ => {
+                self.line(header);
+                self.indent += 1;
+                self.statement(body);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    /// Continues the line of a closing brace that was just rendered, to
+    /// keep it together with the `else`, `catch` or `finally` after it.
+    fn join_brace(&mut self) {
+        if self.output.ends_with("}\n") {
+            self.output.pop();
+            self.output.push(' ');
+            self.continue_line = true;
+        }
+    }
+
+    fn function(&mut self, keyword: &str, function: &Function) {
+        self.doc(function.doc.as_deref());
+        let params = function.written_params().join(", ");
+        let header = format!("{keyword}{}({params})", function.name.token.lexeme);
+        self.block(&header, &function.body);
+    }
+
+    /// Renders `statements` in braces, after `header` if there is one.
+    fn block(&mut self, header: &str, statements: &[Statement]) {
+        let open = if header.is_empty() {
+            "{".to_owned()
+        } else {
+            format!("{header} {{")
+        };
+        if statements.is_empty() {
+            self.line(&format!("{open}}}"));
+            return;
+        }
+        self.line(&open);
+        self.indent += 1;
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn doc(&mut self, doc: Option<&str>) {
+        for line in doc.into_iter().flat_map(str::lines) {
+            self.line(format!("/// {line}").trim_end());
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        if !std::mem::take(&mut self.continue_line) {
+            for _ in 0..self.indent {
+                self.output.push_str("    ");
+            }
+        }
+        self.output.push_str(std::mem::take(&mut self.prefix));
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{Expr, UnaryOp},
+        parser::parse,
+        scanner::scan_tokens,
+        unstable::ast_printer::{print_expr, print_statement},
+    };
+
+    fn parse_source(source: &str) -> Program {
+        parse(scan_tokens(source.as_bytes()).unwrap()).unwrap()
+    }
+
+    /// The s-expression for each statement of `program`, and its result.
+    fn sexprs(program: &Program) -> Vec<String> {
+        let statements = program
+            .statements
+            .iter()
+            .map(|statement| print_statement(program, statement));
+        let result = program.result.map(|result| print_expr(program, result));
+        statements.chain(result).collect()
+    }
+
+    /// Checks that unparsing `source` and parsing the output again gives
+    /// the same tree.
+    fn assert_round_trips(source: &str) {
+        let program = parse_source(source);
+        let output = unparse_program(&program);
+        assert_eq!(
+            sexprs(&parse_source(&output)),
+            sexprs(&program),
+            "unparsed as:\n{output}"
+        );
+    }
+
+    #[test]
+    fn round_trips_every_statement() {
+        assert_round_trips("assert x; assert x > 1, \"too small\";");
+        assert_round_trips("{ var a; { print a; } }");
+        assert_round_trips("outer: while (true) { while (false) { break outer; continue; } }");
+        assert_round_trips(
+            "/// Doc.\nclass A < B { x = 1; y = this.x; init(a) { super.init(a); } m() {} }",
+        );
+        assert_round_trips("f(1, 2)(3); a.b.c = d;");
+        assert_round_trips("for (;;) {} for (var i = 0; i < 3; i = i + 1) print i;");
+        assert_round_trips("a: for (i = 0; i < 3;) continue a;");
+        assert_round_trips("fun f(a, ...rest) { return; } fun g() { return a; }");
+        assert_round_trips("if (a) print 1; else if (b) print 2; else { print 3; }");
+        assert_round_trips("if (a) if (b) print 1; else print 2;");
+        assert_round_trips("throw \"e\"; try { f(); } catch (e) { print e; } finally { g(); }");
+        assert_round_trips("try {} finally {}");
+        assert_round_trips("var a = 1; var b; var (c, d) = (1, 'x'); (c, d) = (d, c); (a,);");
+        assert_round_trips("print -a * (b + c) - !d or e and f == g;");
+        assert_round_trips("1 + 2");
+    }
+
+    #[test]
+    fn parenthesizes_hand_built_trees_as_precedence_requires() {
+        let mut program = parse_source("a; b; c;");
+        let [a, b, c] = [0, 1, 2].map(|index| match program.statements[index].kind {
+            StatementKind::Expression(expr) => expr,
+            _ => unreachable!(),
+        });
+        let add = |program: &mut Program, kind| {
+            let span = program[a].span;
+            program.add_expr(Expr { kind, span })
+        };
+        let binary = |left, operator, right| ExprKind::Binary {
+            left,
+            operator,
+            right,
+        };
+
+        let right = add(&mut program, binary(b, BinaryOp::Subtract, c));
+        let expr = add(&mut program, binary(a, BinaryOp::Subtract, right));
+        assert_eq!(unparse_expr(&program, expr), "a - (b - c)");
+        let left = add(&mut program, binary(a, BinaryOp::Subtract, b));
+        let expr = add(&mut program, binary(left, BinaryOp::Subtract, c));
+        assert_eq!(unparse_expr(&program, expr), "a - b - c");
+        let sum = add(&mut program, binary(a, BinaryOp::Add, b));
+        let expr = add(&mut program, binary(sum, BinaryOp::Multiply, c));
+        assert_eq!(unparse_expr(&program, expr), "(a + b) * c");
+
+        let negated = ExprKind::Unary {
+            operator: UnaryOp::Negate,
+            right: sum,
+        };
+        let expr = add(&mut program, negated);
+        assert_eq!(unparse_expr(&program, expr), "-(a + b)");
+
+        let (ExprKind::Variable(a_name), ExprKind::Variable(c_name)) =
+            (program[a].kind.clone(), program[c].kind.clone())
+        else {
+            unreachable!()
+        };
+        let assign = ExprKind::Assign {
+            name: a_name,
+            value: b,
+        };
+        let assign = add(&mut program, assign);
+        let get = ExprKind::Get {
+            object: assign,
+            name: c_name,
+        };
+        let expr = add(&mut program, get);
+        assert_eq!(unparse_expr(&program, expr), "(a = b).c");
+    }
+}
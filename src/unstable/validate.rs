@@ -0,0 +1,340 @@
+//! Checks that a syntax tree makes sense beyond its grammar: that `this`
+//! and `super` appear in classes, `return` in functions, `break` and
+//! `continue` in loops with the labels they name, tuples are assigned to
+//! variables only, and literals hold literal tokens. The parser runs these checks itself, so this is only
+//! needed for trees built or changed by hand, which the interpreter
+//! assumes to be valid.
+
+use crate::{
+    ast::{ExprId, ExprKind, Function, Program, Statement, StatementKind},
+    parser::{ParseError, ParseErrors},
+    scanner::{Token, TokenType},
+    unstable::stack,
+};
+
+pub fn validate(program: &Program) -> Result<(), ParseErrors> {
+    let mut errors = Vec::new();
+    check(program, &program.statements, program.result, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ParseErrors::new(errors))
+    }
+}
+
+/// Adds the problems with `statements`, and then `result`, to `errors`.
+/// They are checked as top-level code of `program`.
+pub(crate) fn check(
+    program: &Program,
+    statements: &[Statement],
+    result: Option<ExprId>,
+    errors: &mut Vec<ParseError>,
+) {
+    let mut validator = Validator {
+        program,
+        errors,
+        class: ClassKind::None,
+        function: FunctionKind::None,
+        loops: Vec::new(),
+    };
+    for statement in statements {
+        validator.statement(statement);
+    }
+    if let Some(result) = result {
+        validator.expr(result);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Initializer,
+}
+
+struct Validator<'a> {
+    program: &'a Program,
+    errors: &'a mut Vec<ParseError>,
+    /// The kind of the innermost class being checked.
+    class: ClassKind,
+    /// The kind of the innermost function being checked.
+    function: FunctionKind,
+    /// The labels of the loops enclosing the statement being checked,
+    /// innermost last, within the innermost function.
+    loops: Vec<Option<&'a str>>,
+}
+
+impl<'a> Validator<'a> {
+    fn statement(&mut self, statement: &'a Statement) {
+        // Trees built by hand can nest deeper than the parser allows.
+        stack::grow(|| self.check_statement(statement));
+    }
+
+    fn check_statement(&mut self, statement: &'a Statement) {
+        for expr in statement.exprs() {
+            self.expr(expr);
+        }
+        match &statement.kind {
+            StatementKind::Class {
+                superclass,
+                fields,
+                methods,
+                ..
+            } => {
+                let enclosing = self.class;
+                self.class = match superclass {
+                    Some(_) => ClassKind::Subclass,
+                    None => ClassKind::Class,
+                };
+                for field in fields {
+                    self.expr(field.initializer);
+                }
+                for method in methods {
+                    let kind = match method.name.token.lexeme.as_str() {
+                        "init" => FunctionKind::Initializer,
+                        _ => FunctionKind::Function,
+                    };
+                    self.function(method, kind);
+                }
+                self.class = enclosing;
+            }
+            StatementKind::Function(function) => self.function(function, FunctionKind::Function),
+            StatementKind::Return { keyword, value } => match self.function {
+                FunctionKind::None => self.errors.push(ParseError::ReturnOutsideFunction {
+                    keyword: keyword.clone(),
+                }),
+                FunctionKind::Initializer if value.is_some() => {
+                    self.errors.push(ParseError::ReturnFromInitializer {
+                        keyword: keyword.clone(),
+                    });
+                }
+                FunctionKind::Function | FunctionKind::Initializer => {}
+            },
+            StatementKind::Break { keyword, label }
+            | StatementKind::Continue { keyword, label } => match label {
+                _ if self.loops.is_empty() => self.errors.push(ParseError::OutsideLoop {
+                    keyword: keyword.clone(),
+                }),
+                Some(label) if !self.loops.contains(&Some(label.lexeme.as_str())) => {
+                    self.errors.push(ParseError::UnknownLabel {
+                        label: label.clone(),
+                    });
+                }
+                _ => {}
+            },
+            StatementKind::For { label, .. } | StatementKind::While { label, .. } => {
+                self.loops
+                    .push(label.as_ref().map(|label| label.lexeme.as_str()));
+                for child in statement.children() {
+                    self.statement(child);
+                }
+                self.loops.pop();
+            }
+            _ => {
+                for child in statement.children() {
+                    self.statement(child);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, function: &'a Function, kind: FunctionKind) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        // Loops outside a function can't be left from inside it.
+        let loops = std::mem::take(&mut self.loops);
+        for statement in &function.body {
+            self.statement(statement);
+        }
+        self.loops = loops;
+        self.function = enclosing;
+    }
+
+    fn expr(&mut self, expr: ExprId) {
+        stack::grow(|| self.check_expr(expr));
+    }
+
+    fn check_expr(&mut self, expr: ExprId) {
+        match &self.program[expr].kind {
+            ExprKind::Literal(literal) => {
+                if !matches!(
+                    literal.token.token_type,
+                    TokenType::False
+                        | TokenType::True
+                        | TokenType::Nil
+                        | TokenType::Number(_)
+                        | TokenType::String(_)
+                        | TokenType::Char(_)
+                ) {
+                    self.errors.push(ParseError::NotALiteral {
+                        found: literal.token.clone(),
+                    });
+                }
+            }
+            ExprKind::AssignTuple { targets, value } => {
+                for &target in targets {
+                    if !matches!(self.program[target].kind, ExprKind::Variable(_)) {
+                        // The tree keeps no `=`, so the error points at the
+                        // target instead.
+                        let equals = Token {
+                            token_type: TokenType::Equal,
+                            lexeme: "=".to_owned(),
+                            span: self.program[target].span,
+                            doc: None,
+                        };
+                        self.errors
+                            .push(ParseError::InvalidAssignmentTarget { equals });
+                    }
+                    self.expr(target);
+                }
+                self.expr(*value);
+            }
+            ExprKind::This(keyword) if self.class == ClassKind::None => {
+                self.errors.push(ParseError::ThisOutsideClass {
+                    keyword: keyword.token.clone(),
+                });
+            }
+            ExprKind::Super { keyword, .. } => match self.class {
+                ClassKind::None => self.errors.push(ParseError::SuperOutsideClass {
+                    keyword: keyword.token.clone(),
+                }),
+                ClassKind::Class => self.errors.push(ParseError::SuperWithoutSuperclass {
+                    keyword: keyword.token.clone(),
+                }),
+                ClassKind::Subclass => {}
+            },
+            kind => {
+                for child in kind.children() {
+                    self.expr(child);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{BinaryOp, Expr},
+        parser::parse,
+        scanner::scan_tokens,
+    };
+
+    fn parse_source(source: &str) -> Program {
+        parse(scan_tokens(source.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn validates_long_chains() {
+        // `a.b.b…` and `1 + 1 + …`, longer than the parser allows.
+        let mut program = parse_source("a.b; 1;");
+        let StatementKind::Expression(mut get) = program.statements[0].kind else {
+            unreachable!()
+        };
+        let ExprKind::Get { name, .. } = program[get].kind.clone() else {
+            unreachable!()
+        };
+        for _ in 0..200_000 {
+            let span = program[get].span;
+            let kind = ExprKind::Get {
+                object: get,
+                name: name.clone(),
+            };
+            get = program.add_expr(Expr { kind, span });
+        }
+        let StatementKind::Expression(one) = program.statements[1].kind else {
+            unreachable!()
+        };
+        let mut sum = one;
+        for _ in 0..20_000 {
+            let span = program[sum].span;
+            let kind = ExprKind::Binary {
+                left: sum,
+                operator: BinaryOp::Add,
+                right: one,
+            };
+            sum = program.add_expr(Expr { kind, span });
+        }
+        program.statements[0].kind = StatementKind::Expression(get);
+        program.statements[1].kind = StatementKind::Print(sum);
+        assert!(validate(&program).is_ok());
+    }
+
+    #[test]
+    fn reports_what_the_parser_would() {
+        let mut program = parse_source("fun f() { return 1; }");
+        let StatementKind::Function(function) = &mut program.statements[0].kind else {
+            unreachable!()
+        };
+        let body = std::mem::take(&mut std::rc::Rc::make_mut(function).body);
+        program.statements.extend(body);
+        let errors = validate(&program).unwrap_err().to_string();
+        assert_eq!(
+            errors,
+            "[line 1:11] Error at 'return': Can't return from top-level code."
+        );
+    }
+
+    #[test]
+    fn reports_tuples_assigned_to_anything_but_variables() {
+        let mut program = parse_source("(a, b) = (1, 2);\nc.d;");
+        let StatementKind::Expression(assign) = program.statements[0].kind else {
+            unreachable!()
+        };
+        let StatementKind::Expression(get) = program.statements[1].kind else {
+            unreachable!()
+        };
+        let ExprKind::AssignTuple { targets, .. } = &mut program[assign].kind else {
+            unreachable!()
+        };
+        targets[1] = get;
+        program.statements.pop();
+        let errors = validate(&program).unwrap_err().to_string();
+        assert_eq!(
+            errors,
+            "[line 2:1] Error at '=': Invalid assignment target."
+        );
+    }
+
+    #[test]
+    fn reports_jumps_that_leave_no_loop() {
+        let mut program = parse_source("a: while (true) { break a; }\nwhile (true) { continue; }");
+        // Move each jump out of its loop: the labeled one into a loop
+        // without the label, the other to the top level.
+        let [first, second] = &mut program.statements[..] else {
+            unreachable!()
+        };
+        let (StatementKind::While { body: first, .. }, StatementKind::While { body: second, .. }) =
+            (&mut first.kind, &mut second.kind)
+        else {
+            unreachable!()
+        };
+        std::mem::swap(first, second);
+        let StatementKind::Block(statements) = &mut first.kind else {
+            unreachable!()
+        };
+        let jump = statements.pop().unwrap();
+        program.statements.push(jump);
+        let errors = validate(&program).unwrap_err().to_string();
+        assert_eq!(
+            errors,
+            "[line 1:25] Error at 'a': No enclosing loop has this label.\n\
+             [line 2:16] Error at 'continue': Can't use 'continue' outside of a loop."
+        );
+    }
+
+    #[test]
+    fn a_label_names_the_innermost_loop_that_has_it() {
+        // A loop can hold only one label, so `a: a: while` has no tree,
+        // but nested loops may share one.
+        let program = parse_source("a: while (true) { a: while (true) { break a; } continue a; }");
+        assert!(validate(&program).is_ok());
+    }
+}